@@ -1,26 +1,63 @@
 use std::fmt::Display;
 
+pub mod arithmetic;
 pub mod builtin_type;
 pub mod change_directory;
 pub mod echo;
+pub mod exec;
 pub mod history;
+pub mod jobs;
 pub mod pwd;
+pub mod read;
 pub mod run_external_executable;
+pub mod set;
+pub mod shopt;
 
 #[derive(Debug, Clone)]
 pub enum BuiltinCommand {
+    Arithmetic(String),
+    Bg(Vec<String>),
     ChangeDirectory(Vec<String>),
     Echo(Vec<String>),
+    Exec(Vec<String>),
     Exit,
+    Fg(Vec<String>),
     History(Vec<String>),
-    PWD,
+    Jobs(Vec<String>),
+    Pwd,
+    Read(Vec<String>),
+    Set(Vec<String>),
+    Shopt(Vec<String>),
     Type(Vec<String>),
     NotFound(String, Vec<String>),
 }
 
 impl BuiltinCommand {
+    /// The name the builtin was run by, for error messages
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Arithmetic(_) => "((",
+            Self::Bg(_) => "bg",
+            Self::ChangeDirectory(_) => "cd",
+            Self::Echo(_) => "echo",
+            Self::Exec(_) => "exec",
+            Self::Exit => "exit",
+            Self::Fg(_) => "fg",
+            Self::History(_) => "history",
+            Self::Jobs(_) => "jobs",
+            Self::Pwd => "pwd",
+            Self::Read(_) => "read",
+            Self::Set(_) => "set",
+            Self::Shopt(_) => "shopt",
+            Self::Type(_) => "type",
+            Self::NotFound(command_name, _) => command_name,
+        }
+    }
+
     pub fn matches(partial: &str) -> Vec<String> {
-        let possible_matches = ["cd", "echo", "exit", "pwd", "type"];
+        let possible_matches = [
+            "bg", "cd", "echo", "exec", "exit", "fg", "jobs", "pwd", "read", "set", "shopt", "type",
+        ];
 
         possible_matches
             .iter()
@@ -28,20 +65,23 @@ impl BuiltinCommand {
             .map(ToString::to_string)
             .collect()
     }
-
-    pub fn is_builtin(&self) -> bool {
-        !matches!(self, Self::NotFound(_, _))
-    }
 }
 
 impl From<(String, Vec<String>)> for BuiltinCommand {
     fn from((command, arguments): (String, Vec<String>)) -> Self {
         match command.as_str() {
+            "bg" => Self::Bg(arguments),
             "cd" => Self::ChangeDirectory(arguments),
             "echo" => Self::Echo(arguments),
+            "exec" => Self::Exec(arguments),
             "exit" => Self::Exit,
+            "fg" => Self::Fg(arguments),
             "history" => Self::History(arguments),
-            "pwd" => Self::PWD,
+            "jobs" => Self::Jobs(arguments),
+            "pwd" => Self::Pwd,
+            "read" => Self::Read(arguments),
+            "set" => Self::Set(arguments),
+            "shopt" => Self::Shopt(arguments),
             "type" => Self::Type(arguments),
             _ => Self::NotFound(command.to_owned(), arguments),
         }
@@ -58,6 +98,11 @@ impl From<String> for BuiltinCommand {
 impl Display for BuiltinCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let output = match self {
+            BuiltinCommand::Arithmetic(expression) => format!("(({expression}))"),
+            BuiltinCommand::Bg(args) => {
+                let args = args.join(" ");
+                format!("bg {args}")
+            }
             BuiltinCommand::ChangeDirectory(args) => {
                 let path = args.first().cloned().unwrap_or_default();
                 format!("cd {path}")
@@ -66,12 +111,36 @@ impl Display for BuiltinCommand {
                 let args = args.join(" ");
                 format!("echo {args}")
             }
-            BuiltinCommand::Exit => format!("exit"),
+            BuiltinCommand::Exec(args) => {
+                let args = args.join(" ");
+                format!("exec {args}")
+            }
+            BuiltinCommand::Exit => "exit".to_owned(),
+            BuiltinCommand::Fg(args) => {
+                let args = args.join(" ");
+                format!("fg {args}")
+            }
             BuiltinCommand::History(args) => {
                 let args = args.join(" ");
                 format!("history {args}")
             }
-            BuiltinCommand::PWD => format!("pwd"),
+            BuiltinCommand::Jobs(args) => {
+                let args = args.join(" ");
+                format!("jobs {args}")
+            }
+            BuiltinCommand::Pwd => "pwd".to_owned(),
+            BuiltinCommand::Read(args) => {
+                let args = args.join(" ");
+                format!("read {args}")
+            }
+            BuiltinCommand::Set(args) => {
+                let args = args.join(" ");
+                format!("set {args}")
+            }
+            BuiltinCommand::Shopt(args) => {
+                let args = args.join(" ");
+                format!("shopt {args}")
+            }
             BuiltinCommand::Type(args) => {
                 let command = args.first().cloned().unwrap_or_default();
                 format!("type {command}")
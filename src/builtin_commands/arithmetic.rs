@@ -0,0 +1,14 @@
+use crate::{command::CommandIO, errors::ErrorExitCode, expansion::arithmetic::evaluate};
+use std::io::Write;
+
+/// The `(( expression ))` command, which succeeds when the expression is not zero
+pub fn arithmetic(expression: &str, mut command_io: CommandIO) -> Result<(), ErrorExitCode> {
+    match evaluate(expression) {
+        Ok(0) => Err(ErrorExitCode::new_const::<1>()),
+        Ok(_) => Ok(()),
+        Err(error) => {
+            writeln!(command_io.stderr, "{error}")?;
+            Err(ErrorExitCode::new_const::<1>())
+        }
+    }
+}
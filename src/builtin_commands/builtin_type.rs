@@ -44,6 +44,7 @@ pub fn builtin_type(
             .map_err(|_error| ErrorExitCode::new_const::<3>())?;
         Err(ErrorExitCode::new_const::<5>())
     } else {
-        writeln!(command_io.stdout, "{message}").map_err(|_error| ErrorExitCode::new_const::<4>())
+        writeln!(command_io.stdout, "{message}")?;
+        Ok(())
     }
 }
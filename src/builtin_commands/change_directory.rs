@@ -1,6 +1,6 @@
 use crate::{command::CommandIO, errors::ErrorExitCode};
 use std::{
-    env::{home_dir, set_current_dir},
+    env::{self, home_dir, set_current_dir},
     io::Write,
     path::Path,
 };
@@ -22,10 +22,20 @@ pub fn change_directory(
     };
 
     if target_path.is_dir() {
+        let old_directory = env::current_dir().ok();
+
         if let Err(error) = set_current_dir(target_path) {
             writeln!(command_io_in.stderr, "{error:?}")?;
             return Err(ErrorExitCode::new_const::<2>());
         }
+
+        // `~-` and `~+` expand to these
+        if let Some(old_directory) = old_directory {
+            unsafe { env::set_var("OLDPWD", old_directory) }
+        }
+        if let Ok(new_directory) = env::current_dir() {
+            unsafe { env::set_var("PWD", new_directory) }
+        }
     } else {
         let target_path = target_path.to_string_lossy().into_owned();
         writeln!(
@@ -0,0 +1,87 @@
+use super::run_external_executable::move_descriptors;
+use crate::{
+    command::{CommandIO, FileDescriptors, Output},
+    errors::{ErrorExitCode, describe_io_error},
+    utilities::{find_executable_files, move_descriptor},
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Write},
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    process,
+};
+
+/// `exec [command [arguments ...]]`. The redirects are applied to the shell itself, so they
+/// stay in place for every command after this one. With a command, the shell is replaced by
+/// it and never comes back.
+pub fn exec(
+    arguments: &[String],
+    file_descriptors: &FileDescriptors,
+    shell_file_descriptors: &mut BTreeMap<u32, Output>,
+    paths: &[PathBuf],
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let Some((command_name, arguments)) = arguments.split_first() else {
+        let mut kept = BTreeMap::new();
+
+        // these have to be copied before standard out and error move, so `exec 6>&1 >log`
+        // keeps a way back to the terminal in 6
+        for (fd, output) in &file_descriptors.others {
+            if let Some(file) = output.to_file()? {
+                kept.insert(*fd, file);
+            }
+        }
+
+        // anything still buffered has to get out before standard out points somewhere else
+        io::stdout().flush()?;
+
+        for (fd, source) in file_descriptors.duplicate_standard()? {
+            move_descriptor(fd, source.as_ref().map(AsRawFd::as_raw_fd))?;
+        }
+
+        *shell_file_descriptors = kept;
+        return Ok(());
+    };
+
+    // the descriptors are moved in the shell itself right before the program starts, so a
+    // program that can't be found has to be caught before anything gets moved
+    let is_found = command_name.contains('/')
+        || find_executable_files(command_name, paths, false)
+            .is_ok_and(|executables| !executables.is_empty());
+
+    if !is_found {
+        writeln!(command_io.stderr, "exec: {command_name}: not found")?;
+        return Err(ErrorExitCode::new_const::<127>());
+    }
+
+    let standard_descriptors = file_descriptors.duplicate_standard()?;
+    let other_descriptors =
+        file_descriptors.duplicate_others(io::stdout().as_fd(), io::stderr().as_fd())?;
+    let mut command = process::Command::new(command_name);
+
+    command.args(arguments);
+    move_descriptors(
+        &mut command,
+        standard_descriptors.iter().chain(&other_descriptors),
+    );
+    io::stdout().flush()?;
+
+    let error = command.exec();
+
+    // by now standard error is wherever the redirects sent it
+    if error.kind() == ErrorKind::NotFound {
+        eprintln!("exec: {command_name}: not found");
+        return Err(ErrorExitCode::new_const::<127>());
+    }
+
+    writeln!(
+        io::stderr(),
+        "exec: {command_name}: cannot execute: {}",
+        describe_io_error(&error)
+    )?;
+    Err(ErrorExitCode::new_const::<126>())
+}
@@ -1,15 +1,14 @@
 use std::{
     collections::VecDeque,
     env, fs,
-    io::{BufRead, BufReader, BufWriter, Seek, Write},
+    io::{self, BufRead, BufReader, BufWriter, Seek, Write},
     path::{Path, PathBuf},
-    usize,
 };
 
 use anyhow::bail;
 
 use crate::{
-    command::{Command, CommandIO},
+    command::{CommandIO, parse_user_input},
     errors::ErrorExitCode,
 };
 
@@ -20,7 +19,7 @@ pub struct History {
 }
 
 impl History {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(extglob: bool) -> anyhow::Result<Self> {
         let histfile_path = Self::get_history_file_path()?;
 
         if histfile_path.is_dir() {
@@ -31,14 +30,15 @@ impl History {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(histfile_path)?;
 
         let mut commands = vec![];
         let lookback_index = 0;
         let history_file_reader = BufReader::new(history_file);
 
-        for history_file_command in history_file_reader.lines() {
-            let command = history_file_command?.trim().to_owned();
+        for history_file_command in read_commands(history_file_reader, extglob)? {
+            let command = history_file_command.trim().to_owned();
 
             if command.is_empty() {
                 continue;
@@ -52,8 +52,8 @@ impl History {
         })
     }
 
-    pub fn add(&mut self, command: &Command) {
-        let history_item = command.builtin_command.to_string().trim().to_owned();
+    pub fn add(&mut self, user_input_line: &str) {
+        let history_item = user_input_line.trim().to_owned();
 
         self.commands.push(history_item);
     }
@@ -109,12 +109,13 @@ impl History {
         &mut self,
         mut command_io: CommandIO,
         mut arguments: VecDeque<String>,
+        extglob: bool,
     ) -> Result<(), ErrorExitCode> {
         let Some(first_argument) = arguments.pop_front() else {
             return self.print(command_io);
         };
 
-        if let Some(count) = first_argument.as_str().parse::<usize>().ok() {
+        if let Ok(count) = first_argument.as_str().parse::<usize>() {
             return self.print_n(command_io, count);
         }
 
@@ -125,7 +126,7 @@ impl History {
                     return Err(ErrorExitCode::new_const::<1>());
                 };
 
-                self.load_history_from_file(filename, command_io)
+                self.load_history_from_file(filename, command_io, extglob)
             }
             "-w" => {
                 let Some(filename) = arguments.pop_front() else {
@@ -155,6 +156,7 @@ impl History {
         &mut self,
         filename: String,
         mut command_io: CommandIO,
+        extglob: bool,
     ) -> Result<(), ErrorExitCode> {
         let path = Path::new(&filename);
 
@@ -172,13 +174,11 @@ impl History {
         };
         let history_file_reader = BufReader::new(history_file);
 
-        for history_command in history_file_reader.lines() {
-            match history_command {
-                Ok(command) => self.commands.push(command),
-                Err(error) => {
-                    writeln!(command_io.stderr, "{error:?}")?;
-                    return Err(ErrorExitCode::new_const::<4>());
-                }
+        match read_commands(history_file_reader, extglob) {
+            Ok(commands) => self.commands.extend(commands),
+            Err(error) => {
+                writeln!(command_io.stderr, "{error:?}")?;
+                return Err(ErrorExitCode::new_const::<4>());
             }
         }
 
@@ -237,6 +237,7 @@ impl History {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(path)
         {
             Ok(file) => file,
@@ -283,3 +284,31 @@ impl History {
         Ok(path)
     }
 }
+
+/// Reads the commands saved in a history file. A command that was typed over several lines
+/// is saved over several lines as well, so lines are joined for as long as the command they
+/// make up isn't finished, just like they were when it was typed.
+fn read_commands(reader: impl BufRead, extglob: bool) -> io::Result<Vec<String>> {
+    let mut commands = vec![];
+    let mut unfinished_command: Option<String> = None;
+
+    for line in reader.lines() {
+        let line = line?;
+        let command = match unfinished_command.take() {
+            Some(command) => format!("{command}\n{line}"),
+            None => line,
+        };
+
+        if parse_user_input(command.clone(), extglob)
+            .is_err_and(|error| error.is_incomplete_input())
+        {
+            unfinished_command = Some(command);
+        } else {
+            commands.push(command);
+        }
+    }
+
+    commands.extend(unfinished_command);
+
+    Ok(commands)
+}
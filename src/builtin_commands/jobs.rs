@@ -0,0 +1,736 @@
+use crate::{
+    command::{CommandIO, describe_signal, exit_code, report_signal},
+    errors::ErrorExitCode,
+    utilities::duplicate_above,
+};
+use std::{
+    io::{self, Write},
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+    ptr,
+};
+
+/// The jobs the shell has started that haven't finished yet, or haven't been reported as
+/// finished, along with the terminal they take turns in front of.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Job numbers from the one least recently started, stopped or continued to the most
+    /// recent, for working out which jobs `%+` and `%-` are
+    recent: Vec<usize>,
+    /// The process ID of the last command that was put in the background, for `$!`
+    pub last_background_pid: Option<libc::pid_t>,
+    /// Only there when the shell is reading from a terminal, since job control needs one
+    terminal: Option<Terminal>,
+}
+
+impl Jobs {
+    /// Turns on job control if the shell is reading from a terminal. The shell gets a process
+    /// group of its own, so that `Ctrl-C` and `Ctrl-Z` only reach the job in front.
+    pub fn new() -> Self {
+        Self {
+            terminal: Terminal::new(),
+            ..Self::default()
+        }
+    }
+
+    /// Stops a forked copy of the shell from handing the terminal around, while it still
+    /// knows about the jobs for `jobs` to list
+    pub fn leave_terminal(&mut self) {
+        self.terminal = None;
+    }
+
+    /// Where the next process started for `job` should go, which is `None` without job
+    /// control
+    pub fn process_group(&self, job: &Job, foreground: bool) -> Option<ProcessGroup> {
+        let terminal = self.terminal.as_ref()?;
+
+        Some(ProcessGroup {
+            pgid: job.pgid.unwrap_or(0),
+            terminal: foreground.then(|| terminal.fd.as_raw_fd()),
+        })
+    }
+
+    /// Adds a job that was started with `&` and says which number it got
+    pub fn run_in_background(&mut self, job: Job) {
+        self.last_background_pid = job.processes.iter().rev().find_map(|process| process.pid);
+
+        if self.terminal.is_some()
+            && let Some(pid) = self.last_background_pid
+        {
+            eprintln!("[{}] {pid}", self.next_number());
+        }
+
+        self.add(job);
+    }
+
+    /// Hands the terminal to `job` and waits until it finishes or is stopped, returning the
+    /// exit code of each of its processes. A job that was stopped goes in the table.
+    pub fn run_in_foreground(&mut self, mut job: Job) -> io::Result<Vec<i32>> {
+        if let (Some(terminal), Some(pgid)) = (&self.terminal, job.pgid) {
+            // the job's processes take the terminal themselves as well, this fails
+            // harmlessly when they have all finished already
+            let _ = give_terminal_to(terminal.fd.as_raw_fd(), pgid);
+        }
+
+        let result = job.wait(libc::WUNTRACED);
+
+        if let (Some(terminal), Some(_)) = (&self.terminal, job.pgid) {
+            job.modes = terminal.take_back();
+        }
+
+        result?;
+
+        // a pipeline only gets one report, about the last of its commands a signal ended
+        let killed = job
+            .processes
+            .iter()
+            .rev()
+            .find_map(|process| match process.state {
+                ProcessState::Done(exit_status)
+                    if exit_status
+                        .signal()
+                        .is_some_and(|signal| signal != libc::SIGPIPE) =>
+                {
+                    Some(exit_status)
+                }
+                _ => None,
+            });
+
+        if let Some(exit_status) = killed {
+            report_signal(exit_status);
+        }
+
+        let exit_codes = job.exit_codes();
+
+        if job.is_stopped() {
+            eprintln!();
+            let number = self.add(job);
+            eprintln!("{}", self.describe(number, false));
+        }
+
+        Ok(exit_codes)
+    }
+
+    /// Reports the jobs that finished or stopped since the last time, forgetting the ones
+    /// that finished. This happens right before the prompt.
+    pub fn report_changes(&mut self) {
+        for number in self.numbers() {
+            let Some(job) = self.get_mut(number) else {
+                continue;
+            };
+            let was_stopped = job.is_stopped();
+
+            if let Err(error) = job.wait(libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED) {
+                eprintln!("{error}");
+            }
+
+            if job.is_done() || job.is_stopped() && !was_stopped {
+                eprintln!("{}", self.describe(number, false));
+            }
+        }
+
+        self.remove_done();
+    }
+
+    fn add(&mut self, mut job: Job) -> usize {
+        if job.number == 0 {
+            job.number = self.next_number();
+        }
+
+        let number = job.number;
+        let index = self
+            .jobs
+            .partition_point(|other_job| other_job.number < number);
+
+        self.jobs.insert(index, job);
+        self.touch(number);
+        number
+    }
+
+    /// One more than the highest number in use, like bash does
+    fn next_number(&self) -> usize {
+        self.jobs.last().map_or(1, |job| job.number + 1)
+    }
+
+    fn take(&mut self, number: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.number == number)?;
+
+        self.recent.retain(|recent_number| *recent_number != number);
+        Some(self.jobs.remove(index))
+    }
+
+    fn get_mut(&mut self, number: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.number == number)
+    }
+
+    fn numbers(&self) -> Vec<usize> {
+        self.jobs.iter().map(|job| job.number).collect()
+    }
+
+    fn remove_done(&mut self) {
+        for number in self.numbers() {
+            if self.get_mut(number).is_some_and(|job| job.is_done()) {
+                self.take(number);
+            }
+        }
+    }
+
+    /// Makes the job the most recent one
+    fn touch(&mut self, number: usize) {
+        self.recent.retain(|recent_number| *recent_number != number);
+        self.recent.push(number);
+    }
+
+    /// The job numbers with `%+` first and `%-` second. Like in bash, stopped jobs come
+    /// before running ones, and otherwise the most recent job comes first.
+    fn ranked(&self) -> Vec<usize> {
+        let mut ranked = self.recent.iter().rev().copied().collect::<Vec<_>>();
+
+        ranked.sort_by_key(|number| {
+            !self
+                .jobs
+                .iter()
+                .any(|job| job.number == *number && job.is_stopped())
+        });
+        ranked
+    }
+
+    /// `+` for the current job, `-` for the previous one and a space for the rest
+    fn mark(&self, number: usize) -> char {
+        match self.ranked().iter().position(|ranked| *ranked == number) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// Finds the job a job spec refers to, which is `%n` or `n` for job n, `%+` or `%%` for
+    /// the current job, `%-` for the previous one, `%string` for the job whose command starts
+    /// with string and `%?string` for the one whose command contains it. Without a job spec
+    /// it's the current job.
+    fn find(&self, job_spec: Option<&str>) -> Result<usize, String> {
+        let ranked = self.ranked();
+        let Some(job_spec) = job_spec else {
+            return ranked
+                .first()
+                .copied()
+                .ok_or_else(|| "current: no such job".to_owned());
+        };
+        let no_such_job = || format!("{job_spec}: no such job");
+        let spec = job_spec.strip_prefix('%').unwrap_or(job_spec);
+
+        if let Ok(number) = spec.parse::<usize>() {
+            return self
+                .jobs
+                .iter()
+                .any(|job| job.number == number)
+                .then_some(number)
+                .ok_or_else(no_such_job);
+        }
+
+        if !job_spec.starts_with('%') {
+            return Err(no_such_job());
+        }
+
+        match spec {
+            "" | "+" | "%" => ranked.first().copied().ok_or_else(no_such_job),
+            "-" => ranked.get(1).copied().ok_or_else(no_such_job),
+            _ => {
+                let matching = self
+                    .jobs
+                    .iter()
+                    .filter(|job| match spec.strip_prefix('?') {
+                        Some(text) => job.command.contains(text),
+                        None => job.command.starts_with(spec),
+                    })
+                    .map(|job| job.number)
+                    .collect::<Vec<_>>();
+
+                match matching[..] {
+                    [number] => Ok(number),
+                    [] => Err(no_such_job()),
+                    _ => Err(format!("{spec}: ambiguous job spec")),
+                }
+            }
+        }
+    }
+
+    /// A line about the job like `jobs` shows it. With `long` each process gets a line of
+    /// its own with its process ID and its part of the pipeline, like in bash.
+    fn describe(&self, number: usize, long: bool) -> String {
+        let Some(job) = self.jobs.iter().find(|job| job.number == number) else {
+            return String::new();
+        };
+        let mark = self.mark(number);
+        let state = job.state();
+        let background = if job.is_running() { " &" } else { "" };
+
+        if !long {
+            return format!("[{number}]{mark}  {state:<24}{}{background}", job.command);
+        }
+
+        let prefix = format!("[{number}]{mark} ");
+        let mut lines = vec![];
+
+        for (index, process) in job.processes.iter().enumerate() {
+            let pid = process.pid.map(|pid| pid.to_string()).unwrap_or_default();
+
+            if index == 0 {
+                lines.push(format!("{prefix}{pid} {state:<24}{}", process.command));
+            } else {
+                lines.push(format!(
+                    "{:indent$}{pid} {:<22}| {}",
+                    "",
+                    "",
+                    process.command,
+                    indent = prefix.len()
+                ));
+            }
+        }
+
+        format!("{}{background}", lines.join("\n"))
+    }
+}
+
+/// `jobs [-l|-p] [jobspec ...]`. Lists the jobs and whether they are running, stopped or
+/// done, forgetting the ones that are done. `-l` adds the process ID of every process in the
+/// job and `-p` only shows the first one.
+pub fn jobs(
+    arguments: &[String],
+    jobs: &mut Jobs,
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let mut long = false;
+    let mut pids_only = false;
+    let mut job_specs = vec![];
+
+    for argument in arguments {
+        match argument.strip_prefix('-') {
+            Some(flags) if job_specs.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => long = true,
+                        'p' => pids_only = true,
+                        _ => {
+                            writeln!(command_io.stderr, "jobs: -{flag}: invalid option")?;
+                            writeln!(command_io.stderr, "jobs: usage: jobs [-lp] [jobspec ...]")?;
+                            return Err(ErrorExitCode::new_const::<2>());
+                        }
+                    }
+                }
+            }
+            _ => job_specs.push(argument.as_str()),
+        }
+    }
+
+    for job in &mut jobs.jobs {
+        job.wait(libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED)?;
+    }
+
+    let numbers = if job_specs.is_empty() {
+        jobs.numbers()
+    } else {
+        let mut numbers = vec![];
+
+        for job_spec in job_specs {
+            match jobs.find(Some(job_spec)) {
+                Ok(number) => numbers.push(number),
+                Err(message) => {
+                    writeln!(command_io.stderr, "jobs: {message}")?;
+                    return Err(ErrorExitCode::new_const::<1>());
+                }
+            }
+        }
+
+        numbers
+    };
+
+    for number in numbers {
+        if pids_only {
+            let Some(job) = jobs.get_mut(number) else {
+                continue;
+            };
+
+            if let Some(pid) = job.processes.iter().find_map(|process| process.pid) {
+                writeln!(command_io.stdout, "{pid}")?;
+            }
+        } else {
+            writeln!(command_io.stdout, "{}", jobs.describe(number, long))?;
+        }
+    }
+
+    jobs.remove_done();
+    Ok(())
+}
+
+/// `fg [jobspec]`. Continues the job in the foreground and waits for it, so the exit code
+/// is the job's.
+pub fn fg(
+    arguments: &[String],
+    jobs: &mut Jobs,
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let Some(number) = find_job("fg", arguments, jobs, &mut command_io)? else {
+        return Err(ErrorExitCode::new_const::<1>());
+    };
+    let Some(mut job) = jobs.take(number) else {
+        return Err(ErrorExitCode::new_const::<1>());
+    };
+
+    writeln!(command_io.stdout, "{}", job.command)?;
+
+    if let Some(terminal) = &jobs.terminal
+        && let Some(modes) = &job.modes
+    {
+        unsafe { libc::tcsetattr(terminal.fd.as_raw_fd(), libc::TCSADRAIN, modes) };
+    }
+
+    job.resume()?;
+
+    let exit_codes = jobs.run_in_foreground(job)?;
+    ErrorExitCode::from_exit_code(exit_codes.last().copied().unwrap_or_default())
+}
+
+/// `bg [jobspec]`. Continues a stopped job in the background.
+pub fn bg(
+    arguments: &[String],
+    jobs: &mut Jobs,
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let Some(number) = find_job("bg", arguments, jobs, &mut command_io)? else {
+        return Err(ErrorExitCode::new_const::<1>());
+    };
+    let mark = jobs.mark(number);
+    let Some(job) = jobs.get_mut(number) else {
+        return Err(ErrorExitCode::new_const::<1>());
+    };
+
+    if job.is_running() {
+        writeln!(command_io.stderr, "bg: job {number} already in background")?;
+        return Ok(());
+    }
+
+    writeln!(command_io.stdout, "[{number}]{mark} {} &", job.command)?;
+    job.resume()?;
+    jobs.touch(number);
+
+    Ok(())
+}
+
+/// The number of the job `fg` or `bg` was given, or `None` after explaining why there isn't
+/// one
+fn find_job(
+    builtin_name: &str,
+    arguments: &[String],
+    jobs: &Jobs,
+    command_io: &mut CommandIO,
+) -> io::Result<Option<usize>> {
+    if jobs.terminal.is_none() {
+        writeln!(command_io.stderr, "{builtin_name}: no job control")?;
+        return Ok(None);
+    }
+
+    match jobs.find(arguments.first().map(String::as_str)) {
+        Ok(number) => Ok(Some(number)),
+        Err(message) => {
+            writeln!(command_io.stderr, "{builtin_name}: {message}")?;
+            Ok(None)
+        }
+    }
+}
+
+/// A pipeline that the shell started, and the processes running it
+pub struct Job {
+    /// 0 until the job goes in the table
+    number: usize,
+    /// The process group all of the job's processes are in, which is the process ID of the
+    /// first one. It stays `None` without job control.
+    pgid: Option<libc::pid_t>,
+    processes: Vec<Process>,
+    /// The pipeline roughly as it was typed
+    command: String,
+    /// The terminal settings the job had when it was stopped, which it gets back when it
+    /// goes in front again
+    modes: Option<libc::termios>,
+}
+
+impl Job {
+    pub fn new(command: String) -> Self {
+        Self {
+            number: 0,
+            pgid: None,
+            processes: vec![],
+            command,
+            modes: None,
+        }
+    }
+
+    /// Adds a process that was started for the job in `process_group`
+    pub fn add_process(
+        &mut self,
+        pid: libc::pid_t,
+        process_group: Option<ProcessGroup>,
+        command: String,
+    ) {
+        if process_group.is_some() {
+            let pgid = *self.pgid.get_or_insert(pid);
+
+            // the child joins the group itself too, so it's in there whichever of them gets
+            // there first. This fails once the child has started its program, by which time
+            // it's already in.
+            unsafe { libc::setpgid(pid, pgid) };
+        }
+
+        self.processes.push(Process {
+            pid: Some(pid),
+            state: ProcessState::Running,
+            command,
+        });
+    }
+
+    /// Adds a command that finished without a process of its own, like a builtin
+    pub fn add_finished(&mut self, exit_code: i32, command: String) {
+        self.processes.push(Process {
+            pid: None,
+            state: ProcessState::Done(ExitStatus::from_raw((exit_code & 0xff) << 8)),
+            command,
+        });
+    }
+
+    /// The exit code of each process, where a stopped one gets 128 plus the signal that
+    /// stopped it
+    pub fn exit_codes(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|process| match process.state {
+                ProcessState::Running => 0,
+                ProcessState::Stopped(signal) => 128 + signal,
+                ProcessState::Done(exit_status) => exit_code(exit_status),
+            })
+            .collect()
+    }
+
+    /// Waits for each process that hasn't finished to change, or only checks with `WNOHANG`
+    fn wait(&mut self, options: libc::c_int) -> io::Result<()> {
+        for process in &mut self.processes {
+            let Some(pid) = process.pid else {
+                continue;
+            };
+
+            // a stopped process only changes again when something continues it
+            let is_waiting = match process.state {
+                ProcessState::Running => true,
+                ProcessState::Stopped(_) => options & libc::WCONTINUED != 0,
+                ProcessState::Done(_) => false,
+            };
+
+            if !is_waiting {
+                continue;
+            }
+
+            let mut status = 0;
+
+            match unsafe { libc::waitpid(pid, &mut status, options) } {
+                // a forked copy of the shell knows about the jobs but can't wait for them
+                -1 if io::Error::last_os_error().raw_os_error() == Some(libc::ECHILD) => (),
+                -1 => return Err(io::Error::last_os_error()),
+                0 => (),
+                _ if libc::WIFSTOPPED(status) => {
+                    process.state = ProcessState::Stopped(libc::WSTOPSIG(status));
+                }
+                _ if libc::WIFCONTINUED(status) => process.state = ProcessState::Running,
+                _ => process.state = ProcessState::Done(ExitStatus::from_raw(status)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends `SIGCONT` to every process of the job that was stopped
+    fn resume(&mut self) -> io::Result<()> {
+        if let Some(pgid) = self.pgid
+            && unsafe { libc::kill(-pgid, libc::SIGCONT) } == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        for process in &mut self.processes {
+            if matches!(process.state, ProcessState::Stopped(_)) {
+                process.state = ProcessState::Running;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.processes
+            .iter()
+            .any(|process| matches!(process.state, ProcessState::Running))
+    }
+
+    /// Stopped is when none of the processes are running but not all of them have finished
+    fn is_stopped(&self) -> bool {
+        !self.is_running() && !self.is_done()
+    }
+
+    fn is_done(&self) -> bool {
+        self.processes
+            .iter()
+            .all(|process| matches!(process.state, ProcessState::Done(_)))
+    }
+
+    /// What `jobs` says about the job, going by its last process
+    fn state(&self) -> String {
+        if self.is_running() {
+            return "Running".to_owned();
+        }
+
+        let stopped_by = self
+            .processes
+            .iter()
+            .find_map(|process| match process.state {
+                ProcessState::Stopped(signal) => Some(signal),
+                _ => None,
+            });
+
+        if let Some(signal) = stopped_by {
+            return describe_signal(signal);
+        }
+
+        let Some(ProcessState::Done(exit_status)) =
+            self.processes.last().map(|process| process.state)
+        else {
+            return "Done".to_owned();
+        };
+
+        match (exit_status.signal(), exit_status.code()) {
+            (Some(signal), _) if exit_status.core_dumped() => {
+                format!("{} (core dumped)", describe_signal(signal))
+            }
+            (Some(signal), _) => describe_signal(signal),
+            (None, Some(0) | None) => "Done".to_owned(),
+            (None, Some(code)) => format!("Exit {code}"),
+        }
+    }
+}
+
+struct Process {
+    /// `None` for a builtin that ran in the shell itself
+    pid: Option<libc::pid_t>,
+    state: ProcessState,
+    /// Its part of the pipeline, for `jobs -l`
+    command: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ProcessState {
+    Running,
+    /// Stopped by the signal, usually `SIGTSTP` from `Ctrl-Z`
+    Stopped(libc::c_int),
+    Done(ExitStatus),
+}
+
+/// The process group a new process of a job goes in, and the terminal it takes over when
+/// the job is in the foreground
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessGroup {
+    /// 0 starts a new group, led by the process itself
+    pgid: libc::pid_t,
+    terminal: Option<RawFd>,
+}
+
+impl ProcessGroup {
+    /// Moves the calling process into the group. It only makes system calls, so it's fine to
+    /// run between forking and running a program.
+    pub fn join(&self) -> io::Result<()> {
+        if unsafe { libc::setpgid(0, self.pgid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Some(terminal) = self.terminal {
+            give_terminal_to(terminal, unsafe { libc::getpgrp() })?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Terminal {
+    /// A copy of the shell's standard in from when it started, so that redirecting standard
+    /// in with `exec` doesn't lose the terminal
+    fd: OwnedFd,
+    shell_pgid: libc::pid_t,
+    /// The settings the shell expects while it reads the next line, which it gets back
+    /// whenever a job leaves the terminal
+    modes: libc::termios,
+}
+
+impl Terminal {
+    fn new() -> Option<Self> {
+        let stdin = io::stdin();
+
+        if unsafe { libc::isatty(stdin.as_raw_fd()) } == 0 {
+            return None;
+        }
+
+        let fd = duplicate_above(stdin.as_fd(), 10).ok()?;
+
+        // this fails when the shell already leads its own session, which is fine
+        unsafe { libc::setpgid(0, 0) };
+        let shell_pgid = unsafe { libc::getpgrp() };
+
+        give_terminal_to(fd.as_raw_fd(), shell_pgid).ok()?;
+
+        let mut modes = unsafe { mem::zeroed() };
+
+        if unsafe { libc::tcgetattr(fd.as_raw_fd(), &mut modes) } == -1 {
+            return None;
+        }
+
+        Some(Self {
+            fd,
+            shell_pgid,
+            modes,
+        })
+    }
+
+    /// Puts the shell back in front along with its settings, returning the settings the job
+    /// in front left behind
+    fn take_back(&self) -> Option<libc::termios> {
+        let fd = self.fd.as_raw_fd();
+        let mut job_modes = unsafe { mem::zeroed() };
+        let job_modes = (unsafe { libc::tcgetattr(fd, &mut job_modes) } != -1).then_some(job_modes);
+
+        let _ = give_terminal_to(fd, self.shell_pgid);
+        unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &self.modes) };
+
+        job_modes
+    }
+}
+
+/// Makes `pgid` the process group in front of the terminal. `SIGTTOU` is held back while
+/// doing it, otherwise a process that isn't in front would be stopped for trying. It only
+/// makes system calls, so it's fine to run between forking and running a program.
+fn give_terminal_to(terminal: RawFd, pgid: libc::pid_t) -> io::Result<()> {
+    unsafe {
+        let mut blocked = mem::zeroed();
+        let mut previous = mem::zeroed();
+
+        libc::sigemptyset(&mut blocked);
+        libc::sigaddset(&mut blocked, libc::SIGTTOU);
+        libc::sigprocmask(libc::SIG_BLOCK, &blocked, &mut previous);
+
+        let result = libc::tcsetpgrp(terminal, pgid);
+        let error = io::Error::last_os_error();
+
+        libc::sigprocmask(libc::SIG_SETMASK, &previous, ptr::null_mut());
+
+        if result == -1 { Err(error) } else { Ok(()) }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{command::CommandIO, errors::ErrorExitCode, input_parser::is_assignment_name};
+use std::{env, io::Write};
+
+/// `read [-r] [name ...]`. Reads a line from standard in and splits it on `$IFS`, one field
+/// per name with whatever is left over going to the last one. Without a name the whole line
+/// goes into `REPLY`. Without `-r`, a backslash stops the next character from splitting the
+/// line, and one at the end of the line carries on to the next.
+///
+/// Like any builtin in a pipeline, `echo hi | read V` runs in a forked copy of the shell, so
+/// `V` is gone once the pipeline ends, just like in bash. `read V <<< hi` keeps it.
+pub fn read(arguments: &[String], mut command_io: CommandIO) -> Result<(), ErrorExitCode> {
+    let mut raw = false;
+    let mut names = vec![];
+
+    for argument in arguments {
+        match argument.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    if flag != 'r' {
+                        writeln!(command_io.stderr, "read: -{flag}: invalid option")?;
+                        writeln!(command_io.stderr, "read: usage: read [-r] [name ...]")?;
+                        return Err(ErrorExitCode::new_const::<2>());
+                    }
+
+                    raw = true;
+                }
+            }
+            _ => names.push(argument.as_str()),
+        }
+    }
+
+    if let Some(name) = names.iter().find(|name| !is_assignment_name(name)) {
+        writeln!(command_io.stderr, "read: `{name}': not a valid identifier")?;
+        return Err(ErrorExitCode::new_const::<1>());
+    }
+
+    // each character along with whether a backslash escaped it
+    let mut line = vec![];
+    let mut is_complete = false;
+
+    loop {
+        let mut text = String::new();
+
+        if command_io.read_line(&mut text)? == 0 {
+            break;
+        }
+
+        let has_newline = text.ends_with('\n');
+        let mut text_chars = text.trim_end_matches('\n').chars();
+        let mut continues = false;
+
+        while let Some(text_char) = text_chars.next() {
+            if text_char == '\\' && !raw {
+                match text_chars.next() {
+                    Some(escaped_char) => line.push((escaped_char, true)),
+                    None => continues = true,
+                }
+            } else {
+                line.push((text_char, false));
+            }
+        }
+
+        if !has_newline || !continues {
+            is_complete = has_newline;
+            break;
+        }
+    }
+
+    if names.is_empty() {
+        let reply = line
+            .iter()
+            .map(|(line_char, _)| line_char)
+            .collect::<String>();
+        unsafe { env::set_var("REPLY", reply) }
+    } else {
+        let ifs = env::var("IFS").unwrap_or_else(|_| " \t\n".to_owned());
+        let is_separator =
+            |(line_char, is_escaped): &(char, bool)| !is_escaped && ifs.contains(*line_char);
+        let is_blank = |entry: &(char, bool)| is_separator(entry) && entry.0.is_whitespace();
+        let mut rest = line.as_slice();
+
+        for (index, name) in names.iter().enumerate() {
+            let start = rest.iter().position(|entry| !is_blank(entry));
+            rest = &rest[start.unwrap_or(rest.len())..];
+
+            let value = if index == names.len() - 1 {
+                let end = rest.iter().rposition(|entry| !is_blank(entry));
+                &rest[..end.map_or(0, |end| end + 1)]
+            } else {
+                let end = rest.iter().position(is_separator).unwrap_or(rest.len());
+                let value = &rest[..end];
+
+                // a separator is any blanks around at most one separator that isn't a blank,
+                // so `a : b` splits into `a` and `b` with `IFS=' :'`
+                let start = rest[end..].iter().position(|entry| !is_blank(entry));
+                rest = &rest[end + start.unwrap_or(rest.len() - end)..];
+
+                if rest.first().is_some_and(is_separator) {
+                    rest = &rest[1..];
+                }
+
+                value
+            };
+            let value = value
+                .iter()
+                .map(|(line_char, _)| line_char)
+                .collect::<String>();
+
+            unsafe { env::set_var(name, value) }
+        }
+    }
+
+    if !is_complete {
+        return Err(ErrorExitCode::new_const::<1>());
+    }
+
+    Ok(())
+}
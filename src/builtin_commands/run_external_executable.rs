@@ -1,29 +1,84 @@
-use crate::{command::CommandIO, errors::ErrorExitCode};
-use std::process::{self, Child, Stdio};
+use super::jobs::ProcessGroup;
+use crate::{
+    command::{CommandIO, Stream},
+    errors::ErrorExitCode,
+    utilities::move_descriptor,
+};
+use std::{
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::process::CommandExt,
+    },
+    process::{self, Child, Stdio},
+};
 
 pub fn run_external(
     command_name: String,
     arguments: Vec<String>,
     command_io: CommandIO,
-    is_last_child: bool,
-    is_redirecting: bool,
+    process_group: Option<ProcessGroup>,
 ) -> Result<Child, ErrorExitCode> {
     let mut command = process::Command::new(command_name);
 
     command.args(arguments);
-    command.stderr(Stdio::from(command_io.stderr));
     command.env("COLORTERM", "truecolor");
 
-    if let Some(stdio) = command_io.stdin {
-        command.stdin(stdio);
-    }
+    // the descriptors stay open in here until the child has them
+    let mut other_descriptors = command_io.other_descriptors;
+
+    command.stdin(stdio_for(command_io.stdin, 0, &mut other_descriptors));
+    command.stdout(stdio_for(command_io.stdout, 1, &mut other_descriptors));
+    command.stderr(stdio_for(command_io.stderr, 2, &mut other_descriptors));
+    move_descriptors(&mut command, &other_descriptors);
 
-    if !is_last_child {
-        command.stdout(Stdio::piped());
-    } else if is_redirecting {
-        command.stdout(Stdio::from(command_io.stdout));
+    if let Some(process_group) = process_group {
+        unsafe {
+            command.pre_exec(move || process_group.join());
+        }
     }
 
     let child = command.spawn()?;
     Ok(child)
 }
+
+/// A closed stream can't be handed over as it is, so the child gets the shell's copy of `fd`
+/// and closes it before the program starts
+fn stdio_for(
+    stream: Stream,
+    fd: RawFd,
+    other_descriptors: &mut Vec<(RawFd, Option<OwnedFd>)>,
+) -> Stdio {
+    match stream {
+        Stream::Open(file) => Stdio::from(file),
+        Stream::Closed => {
+            other_descriptors.push((fd, None));
+            Stdio::inherit()
+        }
+    }
+}
+
+/// Has the child move each descriptor onto the fd it's paired with, right before the program
+/// starts. The descriptors have to stay open until then.
+pub fn move_descriptors<'a>(
+    command: &mut process::Command,
+    descriptors: impl IntoIterator<Item = &'a (RawFd, Option<OwnedFd>)>,
+) {
+    let raw_descriptors = descriptors
+        .into_iter()
+        .map(|(fd, source)| (*fd, source.as_ref().map(AsRawFd::as_raw_fd)))
+        .collect::<Vec<_>>();
+
+    if raw_descriptors.is_empty() {
+        return;
+    }
+
+    unsafe {
+        command.pre_exec(move || {
+            for (fd, source) in &raw_descriptors {
+                move_descriptor(*fd, *source)?;
+            }
+
+            Ok(())
+        });
+    }
+}
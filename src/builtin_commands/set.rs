@@ -0,0 +1,67 @@
+use super::shopt::ShellOptions;
+use crate::{command::CommandIO, errors::ErrorExitCode};
+use std::io::Write;
+
+/// `set [-C|+C] [-o|+o [option-name]]`, where `-` turns an option on and `+` turns it off.
+/// Without a name, `-o` lists the options and `+o` prints the commands that set them again.
+pub fn set(
+    arguments: &[String],
+    options: &mut ShellOptions,
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let mut arguments = arguments.iter();
+
+    while let Some(argument) = arguments.next() {
+        let (is_on, flags) = if let Some(flags) = argument.strip_prefix('-') {
+            (true, flags)
+        } else if let Some(flags) = argument.strip_prefix('+') {
+            (false, flags)
+        } else {
+            // the rest would be positional parameters, which the shell doesn't have yet
+            break;
+        };
+
+        for flag in flags.chars() {
+            match flag {
+                'C' => options.noclobber = is_on,
+                'o' => match arguments.next() {
+                    Some(name) => {
+                        let Some(value) = options.set_option_mut(name) else {
+                            writeln!(command_io.stderr, "set: {name}: invalid option name")?;
+                            return Err(ErrorExitCode::new_const::<2>());
+                        };
+
+                        *value = is_on;
+                    }
+                    None => {
+                        for name in ShellOptions::SET_NAMES {
+                            let value = options.set_option_mut(name).is_some_and(|value| *value);
+
+                            if is_on {
+                                let state = if value { "on" } else { "off" };
+                                writeln!(command_io.stdout, "{name:<15}\t{state}")?;
+                            } else {
+                                let sign = if value { '-' } else { '+' };
+                                writeln!(command_io.stdout, "set {sign}o {name}")?;
+                            }
+                        }
+                    }
+                },
+                _ => {
+                    writeln!(
+                        command_io.stderr,
+                        "set: {sign}{flag}: invalid option",
+                        sign = &argument[..1]
+                    )?;
+                    writeln!(
+                        command_io.stderr,
+                        "set: usage: set [-C] [-o option-name] [arg ...]"
+                    )?;
+                    return Err(ErrorExitCode::new_const::<2>());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
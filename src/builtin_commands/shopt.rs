@@ -0,0 +1,134 @@
+use crate::{command::CommandIO, errors::ErrorExitCode};
+use std::io::Write;
+
+/// The options that `shopt` and `set -o` switch on and off
+#[derive(Debug, Default)]
+pub struct ShellOptions {
+    /// Allow `*`, `?` and `[...]` to match names starting with `.`
+    pub dotglob: bool,
+    /// Understand `?(...)`, `*(...)`, `+(...)`, `@(...)` and `!(...)` in patterns
+    pub extglob: bool,
+    /// Fail the command when a pattern doesn't match any files
+    pub failglob: bool,
+    /// Let `**` match any number of directories
+    pub globstar: bool,
+    /// Remove patterns that don't match any files instead of keeping them as they are
+    pub nullglob: bool,
+    /// Refuse to let `>` overwrite a file that already exists, which `>|` still can. This
+    /// one belongs to `set -o` rather than `shopt`.
+    pub noclobber: bool,
+    /// Make a pipeline fail when any of its commands do, not just the last one. Also set
+    /// with `set -o`.
+    pub pipefail: bool,
+}
+
+impl ShellOptions {
+    const NAMES: [&str; 5] = ["dotglob", "extglob", "failglob", "globstar", "nullglob"];
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "extglob" => Some(&mut self.extglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+
+    /// The options `set -o` knows about
+    pub(super) const SET_NAMES: [&str; 2] = ["noclobber", "pipefail"];
+
+    pub(super) fn set_option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "noclobber" => Some(&mut self.noclobber),
+            "pipefail" => Some(&mut self.pipefail),
+            _ => None,
+        }
+    }
+}
+
+/// `shopt [-s|-u] [-pq] [name ...]`. Without `-s` or `-u` the named options, or all of them,
+/// are printed, and the exit code says whether they are all on.
+pub fn shopt(
+    arguments: &[String],
+    options: &mut ShellOptions,
+    mut command_io: CommandIO,
+) -> Result<(), ErrorExitCode> {
+    let mut set_to = None;
+    let mut print_reusable = false;
+    let mut quiet = false;
+    let mut names = vec![];
+
+    for argument in arguments {
+        match argument.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        's' => set_to = Some(true),
+                        'u' => set_to = Some(false),
+                        'p' => print_reusable = true,
+                        'q' => quiet = true,
+                        _ => {
+                            writeln!(command_io.stderr, "shopt: -{flag}: invalid option")?;
+                            writeln!(
+                                command_io.stderr,
+                                "shopt: usage: shopt [-pqsu] [optname ...]"
+                            )?;
+                            return Err(ErrorExitCode::new_const::<2>());
+                        }
+                    }
+                }
+            }
+            _ => names.push(argument.as_str()),
+        }
+    }
+
+    if names.is_empty() {
+        names = ShellOptions::NAMES.to_vec();
+
+        // `shopt -s` on its own lists the options that are on, like bash does
+        if let Some(is_on) = set_to.take() {
+            names.retain(|name| options.get_mut(name).is_some_and(|value| *value == is_on));
+        }
+    }
+
+    let mut all_on = true;
+    let mut invalid_name = false;
+
+    for name in names {
+        let Some(value) = options.get_mut(name) else {
+            writeln!(
+                command_io.stderr,
+                "shopt: {name}: invalid shell option name"
+            )?;
+            invalid_name = true;
+            continue;
+        };
+
+        if let Some(is_on) = set_to {
+            *value = is_on;
+            continue;
+        }
+
+        all_on &= *value;
+
+        if quiet {
+            continue;
+        }
+
+        if print_reusable {
+            let flag = if *value { "-s" } else { "-u" };
+            writeln!(command_io.stdout, "shopt {flag} {name}")?;
+        } else {
+            let state = if *value { "on" } else { "off" };
+            writeln!(command_io.stdout, "{name:<15}\t{state}")?;
+        }
+    }
+
+    if invalid_name || !all_on {
+        return Err(ErrorExitCode::new_const::<1>());
+    }
+
+    Ok(())
+}
@@ -1,15 +1,33 @@
 use crate::{
+    Shell,
     builtin_commands::BuiltinCommand,
     errors::CustomError,
-    input_parser::{input_for_one_command, parse_input},
+    expansion::{expand_to_string, expand_word, expand_words},
+    input_parser::{
+        Operator, RedirectKind, Token, Word, input_for_one_command, input_for_one_pipeline,
+        parse_input,
+    },
+    utilities::duplicate_above,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::CStr,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, PipeReader, PipeWriter, Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+    rc::Rc,
+    thread,
 };
-use std::{collections::VecDeque, io::PipeWriter, process::Stdio};
 
 #[derive(Debug, Clone)]
 pub struct Command {
     pub builtin_command: BuiltinCommand,
-    pub standard_out: Output,
-    pub standard_error: Output,
+    pub file_descriptors: FileDescriptors,
 }
 
 impl Command {
@@ -27,108 +45,750 @@ impl Command {
     //         standard_error,
     //     })
     // }
-    pub fn new(mut user_input: VecDeque<String>) -> Result<Option<Self>, CustomError> {
-        let Some(command_name) = user_input.pop_front() else {
+    /// Expands a command as it was typed into the arguments and outputs it will run with.
+    /// Returns `None` when nothing is left to run, as with an unset `$EMPTY`.
+    pub fn new(
+        simple_command: &SimpleCommand,
+        shell: &mut Shell,
+    ) -> Result<Option<Self>, CustomError> {
+        let builtin_command = match &simple_command.arithmetic {
+            Some(expression) => Some(BuiltinCommand::Arithmetic(expand_to_string(
+                expression, shell,
+            )?)),
+            None => {
+                let mut arguments = expand_words(&simple_command.words, shell)?;
+
+                (!arguments.is_empty()).then(|| {
+                    let command_name = arguments.remove(0);
+                    BuiltinCommand::from((command_name, arguments))
+                })
+            }
+        };
+        let mut file_descriptors = FileDescriptors {
+            others: shell.file_descriptors.clone(),
+            ..FileDescriptors::default()
+        };
+
+        // redirects apply from left to right, so `> log 2>&1` sends both to the log while
+        // `2>&1 > log` leaves standard error where standard out was before
+        for redirect in &simple_command.redirects {
+            file_descriptors.apply(redirect, shell)?;
+        }
+
+        let Some(builtin_command) = builtin_command else {
             return Ok(None);
         };
-        let (arguments, command_stdout, command_stderr) = Self::extract_redirect(user_input)?;
-        let builtin_command = BuiltinCommand::from((command_name, arguments));
 
         Ok(Some(Self {
             builtin_command,
-            standard_out: command_stdout,
-            standard_error: command_stderr,
+            file_descriptors,
         }))
     }
+}
 
-    fn extract_redirect(
-        input: VecDeque<String>,
-    ) -> Result<(Vec<String>, Output, Output), CustomError> {
-        let mut arguments = vec![];
-        let mut arguments_iter = input.into_iter();
-        let mut standard_out_output = Output::Standard;
-        let mut standard_error_output = Output::Standard;
+/// What each of a command's file descriptors refers to once its redirects have been applied
+#[derive(Debug, Clone)]
+pub struct FileDescriptors {
+    pub standard_in: Input,
+    pub standard_out: Output,
+    pub standard_error: Output,
+    /// The descriptors from 3 up, as opened by `3>trace.log` or `4<input`
+    pub others: BTreeMap<u32, Output>,
+}
 
-        while let Some(argument) = arguments_iter.next() {
-            match argument.as_str() {
-                "1>" | ">" => {
-                    let Some(next_argument) = arguments_iter.next() else {
-                        return Err(CustomError::FilenameMissing);
-                    };
-                    standard_out_output = Output::CreateFile(next_argument);
+impl Default for FileDescriptors {
+    fn default() -> Self {
+        Self {
+            standard_in: Input::Standard,
+            standard_out: Output::Standard,
+            standard_error: Output::StandardError,
+            others: BTreeMap::new(),
+        }
+    }
+}
+
+impl FileDescriptors {
+    /// Points the redirect's fd at its target
+    fn apply(&mut self, redirect: &Redirect, shell: &mut Shell) -> Result<(), CustomError> {
+        match redirect.kind {
+            RedirectKind::Write => {
+                let output =
+                    Output::create(redirect.expand_target(shell)?, shell.options.noclobber)?;
+                self.set(redirect.fd, output);
+            }
+            RedirectKind::Clobber => {
+                let output = Output::open(redirect.expand_target(shell)?, false)?;
+                self.set(redirect.fd, output);
+            }
+            RedirectKind::Append => {
+                let output = Output::open(redirect.expand_target(shell)?, true)?;
+                self.set(redirect.fd, output);
+            }
+            RedirectKind::WriteBoth | RedirectKind::AppendBoth => {
+                let target = redirect.expand_target(shell)?;
+                let output = if matches!(redirect.kind, RedirectKind::AppendBoth) {
+                    Output::open(target, true)?
+                } else {
+                    Output::create(target, shell.options.noclobber)?
+                };
+
+                self.standard_out = output.clone();
+                self.standard_error = output;
+            }
+            RedirectKind::Duplicate | RedirectKind::DuplicateInput => {
+                let target = redirect.expand_target(shell)?;
+
+                if target == "-" {
+                    self.set(redirect.fd, Output::Closed);
+                } else if let Ok(source_fd) = target.parse() {
+                    let output = self.get(source_fd)?;
+                    self.set(redirect.fd, output);
+                } else if matches!(redirect.kind, RedirectKind::Duplicate) && redirect.fd == 1 {
+                    // `>&file` is an old way of writing `&>file`
+                    self.standard_out = Output::create(target, shell.options.noclobber)?;
+                    self.standard_error = self.standard_out.clone();
+                } else {
+                    return Err(CustomError::BadFileDescriptor(target));
                 }
-                "1>>" | ">>" => {
-                    let Some(next_argument) = arguments_iter.next() else {
-                        return Err(CustomError::FilenameMissing);
-                    };
-                    standard_out_output = Output::AppendFile(next_argument);
+            }
+            RedirectKind::Read | RedirectKind::ReadWrite => {
+                let filename = redirect.expand_target(shell)?;
+                let file = if matches!(redirect.kind, RedirectKind::Read) {
+                    File::open(&filename)
+                } else {
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(&filename)
+                };
+                let file = file.map_err(|error| CustomError::Redirect(filename, error))?;
+
+                self.set(redirect.fd, Output::File(Rc::new(file)));
+            }
+            RedirectKind::HereDocument { .. } => {
+                self.standard_in = Input::Text(expand_to_string(&redirect.target, shell)?);
+            }
+            RedirectKind::HereString => {
+                let text = expand_to_string(&redirect.target, shell)?;
+                self.standard_in = Input::Text(format!("{text}\n"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// What `fd` refers to, for duplicating it with `>&` or `<&`
+    fn get(&self, fd: u32) -> Result<Output, CustomError> {
+        let output = match fd {
+            0 => match &self.standard_in {
+                Input::File(file) => Some(Output::File(file.clone())),
+                Input::Standard => duplicate_above(io::stdin().as_fd(), 3)
+                    .ok()
+                    .map(|copy| Output::File(Rc::new(File::from(copy)))),
+                Input::Text(_) => None,
+                Input::Closed => Some(Output::Closed),
+            },
+            1 => Some(self.standard_out.clone()),
+            2 => Some(self.standard_error.clone()),
+            _ => self.others.get(&fd).cloned(),
+        };
+
+        output.ok_or_else(|| CustomError::BadFileDescriptor(fd.to_string()))
+    }
+
+    fn set(&mut self, fd: u32, output: Output) {
+        match fd {
+            0 => {
+                self.standard_in = match output {
+                    Output::File(file) => Input::File(file),
+                    Output::Closed => Input::Closed,
+                    Output::Standard | Output::StandardError => Input::Standard,
                 }
-                "2>" => {
-                    let Some(next_argument) = arguments_iter.next() else {
-                        return Err(CustomError::FilenameMissing);
-                    };
+            }
+            1 => self.standard_out = output,
+            2 => self.standard_error = output,
+            _ => {
+                self.others.insert(fd, output);
+            }
+        }
+    }
+
+    /// Copies of the descriptors from 3 up, for a child process to move into place, where
+    /// `Standard` and `StandardError` are copies of `standard_out` and `standard_error`
+    pub fn duplicate_others(
+        &self,
+        standard_out: BorrowedFd,
+        standard_error: BorrowedFd,
+    ) -> io::Result<Vec<(RawFd, Option<OwnedFd>)>> {
+        let lowest_unused = self.lowest_unused();
+
+        self.others
+            .iter()
+            .map(|(fd, output)| {
+                let copy = output.duplicate(standard_out, standard_error, lowest_unused)?;
+                Ok((*fd as RawFd, copy))
+            })
+            .collect()
+    }
+
+    /// Copies of standard in, out and error, for `exec` to move into place in the shell itself
+    pub fn duplicate_standard(&self) -> io::Result<Vec<(RawFd, Option<OwnedFd>)>> {
+        let shell_standard_out = io::stdout();
+        let shell_standard_error = io::stderr();
+        let standard_out = shell_standard_out.as_fd();
+        let standard_error = shell_standard_error.as_fd();
 
-                    standard_error_output = Output::CreateFile(next_argument);
+        Ok(vec![
+            (0, self.standard_in.duplicate(3)?),
+            (
+                1,
+                self.standard_out
+                    .duplicate(standard_out, standard_error, 3)?,
+            ),
+            (
+                2,
+                self.standard_error
+                    .duplicate(standard_out, standard_error, 3)?,
+            ),
+        ])
+    }
+
+    /// The copies are numbered above every descriptor they will be moved onto, so that moving
+    /// one into place can't close another
+    fn lowest_unused(&self) -> RawFd {
+        self.others.keys().last().map_or(3, |fd| *fd as RawFd + 1)
+    }
+}
+
+/// A command as it was typed, before any of its words have been expanded
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    /// The expression of an `(( expression ))` command, which takes the place of the words
+    pub arithmetic: Option<Word>,
+}
+
+impl SimpleCommand {
+    pub fn new(input: VecDeque<Token>) -> Result<Option<Self>, CustomError> {
+        let simple_command = Self::extract_redirect(input)?;
+
+        if simple_command.words.is_empty()
+            && simple_command.redirects.is_empty()
+            && simple_command.arithmetic.is_none()
+        {
+            return Ok(None);
+        }
+
+        if simple_command.arithmetic.is_some()
+            && let Some(word) = simple_command.words.first()
+        {
+            return Err(CustomError::UnexpectedToken(word.to_string()));
+        }
+
+        Ok(Some(simple_command))
+    }
+
+    fn extract_redirect(input: VecDeque<Token>) -> Result<Self, CustomError> {
+        let mut words = vec![];
+        let mut redirects = vec![];
+        let mut arithmetic = None;
+        let mut tokens_iter = input.into_iter();
+
+        while let Some(token) = tokens_iter.next() {
+            match token {
+                Token::Word(word) => words.push(word),
+                Token::Arithmetic(expression) if words.is_empty() && arithmetic.is_none() => {
+                    arithmetic = Some(expression);
+                }
+                Token::Arithmetic(_) => {
+                    return Err(CustomError::UnexpectedToken("((".to_owned()));
                 }
-                "2>>" => {
-                    let Some(next_argument) = arguments_iter.next() else {
-                        return Err(CustomError::FilenameMissing);
+                Token::Operator(operator @ Operator::Redirect { fd, kind }) => {
+                    let target = match tokens_iter.next() {
+                        Some(Token::Word(word)) => word,
+                        Some(Token::Operator(operator)) => {
+                            return Err(CustomError::UnexpectedToken(operator.to_string()));
+                        }
+                        Some(Token::Arithmetic(_)) => {
+                            return Err(CustomError::UnexpectedToken("((".to_owned()));
+                        }
+                        None => return Err(CustomError::FilenameMissing),
                     };
 
-                    standard_error_output = Output::AppendFile(next_argument);
+                    // here-documents are only ever read as standard in
+                    if matches!(
+                        kind,
+                        RedirectKind::HereDocument { .. } | RedirectKind::HereString
+                    ) && fd != 0
+                    {
+                        return Err(CustomError::UnsupportedRedirect(operator.to_string()));
+                    }
+
+                    redirects.push(Redirect { fd, kind, target });
+                }
+                Token::Operator(operator) => {
+                    return Err(CustomError::UnexpectedToken(operator.to_string()));
                 }
-                _ => arguments.push(argument),
             }
         }
 
-        Ok((arguments, standard_out_output, standard_error_output))
+        Ok(Self {
+            words,
+            redirects,
+            arithmetic,
+        })
+    }
+}
+
+impl Display for SimpleCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if let Some(expression) = &self.arithmetic {
+            parts.push(format!("(( {expression} ))"));
+        }
+
+        parts.extend(self.words.iter().map(ToString::to_string));
+        parts.extend(self.redirects.iter().map(ToString::to_string));
+
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: u32,
+    pub kind: RedirectKind,
+    pub target: Word,
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = Operator::Redirect {
+            fd: self.fd,
+            kind: self.kind,
+        };
+
+        // the target of a here-document is its whole text, which is too much to show
+        if matches!(self.kind, RedirectKind::HereDocument { .. }) {
+            write!(f, "{operator}")
+        } else {
+            write!(f, "{operator}{}", self.target)
+        }
+    }
+}
+
+impl Redirect {
+    /// Expands the filename being redirected to, which has to stay a single word
+    fn expand_target(&self, shell: &mut Shell) -> Result<String, CustomError> {
+        let mut fields = expand_word(&self.target, shell)?;
+
+        if fields.len() != 1 {
+            return Err(CustomError::AmbiguousRedirect(self.target.to_string()));
+        }
+
+        Ok(fields.remove(0))
+    }
+}
+
+/// Where a command reads from, when it isn't just the terminal or the previous command in
+/// the pipeline
+#[derive(Debug, Clone)]
+pub enum Input {
+    Standard,
+    /// The text of a here-document or here-string
+    Text(String),
+    /// A file opened by `<` or `<>`
+    File(Rc<File>),
+    /// Closed with `<&-`
+    Closed,
+}
+
+impl Input {
+    /// Opens where this input comes from for a command to read. `Standard` is the previous
+    /// command in the pipeline when there is one, otherwise the shell's own standard in.
+    pub fn open_reader(&self, previous_command: Option<PipeReader>) -> io::Result<Stream> {
+        let fd = match (self, previous_command) {
+            (Self::Standard, Some(previous_command)) => previous_command.into(),
+            (Self::Standard, None) => io::stdin().as_fd().try_clone_to_owned()?,
+            (Self::Text(text), _) => {
+                let (text_reader, mut text_writer) = io::pipe()?;
+                let text = text.clone();
+
+                // written from another thread so that text bigger than the pipe can hold
+                // doesn't block us before the command starts reading
+                thread::spawn(move || text_writer.write_all(text.as_bytes()));
+                text_reader.into()
+            }
+            (Self::File(file), _) => file.try_clone()?.into(),
+            (Self::Closed, _) => return Ok(Stream::Closed),
+        };
+
+        Ok(Stream::Open(File::from(fd)))
+    }
+
+    /// A copy of the descriptor numbered `lowest_unused` or above, or `None` when it is closed
+    fn duplicate(&self, lowest_unused: RawFd) -> io::Result<Option<OwnedFd>> {
+        match self.open_reader(None)?.as_fd() {
+            Some(fd) => duplicate_above(fd, lowest_unused).map(Some),
+            None => Ok(None),
+        }
     }
 }
 
+/// Where one of a command's outputs ends up, or what any descriptor from 3 up refers to
 #[derive(Debug, Clone)]
 pub enum Output {
+    /// Standard out, which is the next command when there is one in the pipeline
     Standard,
-    CreateFile(String),
-    AppendFile(String),
+    /// The shell's standard error
+    StandardError,
+    /// A file opened by a redirect. Outputs redirected to the same file share it, so they
+    /// write one after the other instead of over each other. Files opened for reading with
+    /// `4<input` end up here too.
+    File(Rc<File>),
+    /// Closed with `>&-`, so a command that writes to it gets an error
+    Closed,
 }
 
 impl Output {
-    pub fn is_standard(&self) -> bool {
-        matches!(self, Self::Standard)
+    /// Opens `filename` for `>`. With `noclobber` set, a regular file that is already there
+    /// is left alone, though something like `/dev/null` can still be written to.
+    fn create(filename: String, noclobber: bool) -> Result<Self, CustomError> {
+        if !noclobber {
+            return Self::open(filename, false);
+        }
+
+        let exists = match fs::metadata(&filename) {
+            Ok(metadata) if metadata.is_file() => {
+                return Err(CustomError::CannotOverwrite(filename));
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        };
+        // `create_new` makes sure nothing else created the file in the meantime
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(!exists)
+            .open(&filename)
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::AlreadyExists => CustomError::CannotOverwrite(filename.clone()),
+                _ => CustomError::Redirect(filename.clone(), error),
+            })?;
+
+        Ok(Self::File(Rc::new(file)))
     }
-}
 
-pub fn parse_user_input(user_input: String) -> Result<VecDeque<Command>, CustomError> {
-    let mut commands = VecDeque::new();
-    let mut parsed_input = parse_input(user_input);
+    /// Opens `filename` for writing, emptying it first unless `append` is set
+    fn open(filename: String, append: bool) -> Result<Self, CustomError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(!append)
+            .append(append)
+            .open(&filename)
+            .map_err(|error| CustomError::Redirect(filename, error))?;
 
-    loop {
-        let command_input = input_for_one_command(&mut parsed_input);
-        let Some(command) = Command::new(command_input)? else {
-            break;
+        Ok(Self::File(Rc::new(file)))
+    }
+
+    /// The output as a file that can outlive the command, for `exec` to keep. `Standard` and
+    /// `StandardError` become copies of the shell's own standard out and error, as they are
+    /// right now.
+    pub fn to_file(&self) -> io::Result<Option<Self>> {
+        let copy = match self {
+            Self::Standard => duplicate_above(io::stdout().as_fd(), 3)?,
+            Self::StandardError => duplicate_above(io::stderr().as_fd(), 3)?,
+            Self::File(_) => return Ok(Some(self.clone())),
+            Self::Closed => return Ok(None),
+        };
+
+        Ok(Some(Self::File(Rc::new(File::from(copy)))))
+    }
+
+    /// A copy of the descriptor numbered `lowest_unused` or above, or `None` when it is closed
+    fn duplicate(
+        &self,
+        standard_out: BorrowedFd,
+        standard_error: BorrowedFd,
+        lowest_unused: RawFd,
+    ) -> io::Result<Option<OwnedFd>> {
+        let fd = match self {
+            Self::Standard => standard_out,
+            Self::StandardError => standard_error,
+            Self::File(file) => file.as_fd(),
+            Self::Closed => return Ok(None),
+        };
+
+        duplicate_above(fd, lowest_unused).map(Some)
+    }
+
+    /// Opens where this output goes for a command to write to. `Standard` is the pipe to
+    /// the next command when there is one, otherwise the shell's own standard out.
+    pub fn open_writer(&self, next_command: Option<&PipeWriter>) -> io::Result<Stream> {
+        let fd = match (self, next_command) {
+            (Self::Standard, Some(next_command)) => next_command.try_clone()?.into(),
+            (Self::Standard, None) => io::stdout().as_fd().try_clone_to_owned()?,
+            (Self::StandardError, _) => io::stderr().as_fd().try_clone_to_owned()?,
+            (Self::File(file), _) => file.try_clone()?.into(),
+            (Self::Closed, _) => return Ok(Stream::Closed),
         };
 
-        commands.push_back(command);
+        Ok(Stream::Open(File::from(fd)))
+    }
+}
+
+/// One of a command's standard in, out or error, which can be closed with `<&-` or `>&-`
+#[derive(Debug)]
+pub enum Stream {
+    Open(File),
+    /// Reading or writing fails with `EBADF`, and a program gets the fd closed
+    Closed,
+}
+
+impl Stream {
+    pub fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        match self {
+            Self::Open(file) => Some(file.as_fd()),
+            Self::Closed => None,
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Open(file) => Ok(Self::Open(file.try_clone()?)),
+            Self::Closed => Ok(Self::Closed),
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        match self {
+            Self::Open(file) => Ok(file),
+            Self::Closed => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file()?.read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Open(file) => file.flush(),
+            // nothing could have been written to flush
+            Self::Closed => Ok(()),
+        }
+    }
+}
+
+/// The exit code of a process that has finished. One that was killed by a signal gets 128
+/// plus the signal number.
+pub fn exit_code(exit_status: ExitStatus) -> i32 {
+    match exit_status.signal() {
+        Some(signal) => 128 + signal,
+        None => exit_status.code().unwrap_or_default(),
+    }
+}
+
+/// Shows what happened to a process that was killed by a signal, like bash does for a
+/// command that was waited for
+pub fn report_signal(exit_status: ExitStatus) {
+    let Some(signal) = exit_status.signal() else {
+        return;
+    };
+
+    match signal {
+        // the terminal already shows the `^C`, this just moves on to the next line
+        libc::SIGINT => eprintln!(),
+        // commands early in a pipeline often end this way when a later one stops reading
+        libc::SIGPIPE => (),
+        _ if exit_status.core_dumped() => eprintln!("{} (core dumped)", describe_signal(signal)),
+        _ => eprintln!("{}", describe_signal(signal)),
     }
+}
+
+/// The name the system gives a signal, like `Terminated` or `Segmentation fault`
+pub fn describe_signal(signal: libc::c_int) -> String {
+    let description = unsafe { CStr::from_ptr(libc::strsignal(signal)) };
+    description.to_string_lossy().into_owned()
+}
+
+/// Decides whether a pipeline in a command list runs, based on the exit status of the
+/// pipeline that ran before it.
+#[derive(Debug, Clone, Copy)]
+pub enum ListOperator {
+    /// `;` or the start of the line, always runs
+    Sequence,
+    /// `&&`, runs when the previous pipeline succeeded
+    And,
+    /// `||`, runs when the previous pipeline failed
+    Or,
+}
+
+impl ListOperator {
+    pub fn should_run(&self, last_exit_code: i32) -> bool {
+        match self {
+            Self::Sequence => true,
+            Self::And => last_exit_code == 0,
+            Self::Or => last_exit_code != 0,
+        }
+    }
+}
+
+impl TryFrom<Operator> for ListOperator {
+    type Error = CustomError;
+
+    fn try_from(operator: Operator) -> Result<Self, Self::Error> {
+        match operator {
+            Operator::Semicolon | Operator::Background => Ok(Self::Sequence),
+            Operator::And => Ok(Self::And),
+            Operator::Or => Ok(Self::Or),
+            _ => Err(CustomError::UnexpectedToken(operator.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub list_operator: ListOperator,
+    pub commands: VecDeque<SimpleCommand>,
+    /// Set by a `&` after the pipeline, so the shell doesn't wait for it
+    pub background: bool,
+}
+
+impl Pipeline {
+    pub fn new(
+        list_operator: ListOperator,
+        mut pipeline_input: VecDeque<Token>,
+    ) -> Result<Self, CustomError> {
+        let mut commands = VecDeque::new();
+
+        if pipeline_input.back().is_some_and(|token| {
+            token.is_operator(Operator::Pipe) || token.is_operator(Operator::PipeBoth)
+        }) {
+            return Err(CustomError::UnexpectedEndOfInput);
+        }
+
+        while !pipeline_input.is_empty() {
+            let command_input = input_for_one_command(&mut pipeline_input);
+            let Some(command) = SimpleCommand::new(command_input)? else {
+                return Err(CustomError::UnexpectedToken("|".to_owned()));
+            };
+
+            commands.push_back(command);
+        }
+
+        Ok(Self {
+            list_operator,
+            commands,
+            background: false,
+        })
+    }
+}
+
+/// The pipeline roughly as it was typed, for `jobs` to show
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, command) in self.commands.iter().enumerate() {
+            if index > 0 {
+                write!(f, " | ")?;
+            }
 
-    Ok(commands)
+            write!(f, "{command}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a line of user input into a list of pipelines separated by `;`, `&`, `&&` and `||`.
+pub fn parse_user_input(
+    user_input: String,
+    extglob: bool,
+) -> Result<VecDeque<Pipeline>, CustomError> {
+    let mut pipelines = VecDeque::new();
+    let mut parsed_input = parse_input(user_input, extglob)?;
+    let mut list_operator = ListOperator::Sequence;
+
+    while !parsed_input.is_empty() {
+        let (pipeline_input, next_operator) = input_for_one_pipeline(&mut parsed_input);
+
+        if pipeline_input.is_empty() {
+            let unexpected_token = next_operator.map(|operator| operator.to_string());
+            return Err(CustomError::UnexpectedToken(
+                unexpected_token.unwrap_or_default(),
+            ));
+        }
+
+        let mut pipeline = Pipeline::new(list_operator, pipeline_input)?;
+        pipeline.background = next_operator == Some(Operator::Background);
+        pipelines.push_back(pipeline);
+
+        if let Some(next_operator) = next_operator {
+            list_operator = ListOperator::try_from(next_operator)?;
+
+            if !matches!(list_operator, ListOperator::Sequence) && parsed_input.is_empty() {
+                return Err(CustomError::UnexpectedEndOfInput);
+            }
+        }
+    }
+
+    Ok(pipelines)
 }
 
 /// Thanks to Justus_Flegel for help with pipes and this pattern.
 /// we've only partially implemented it so far
 /// https://play.rust-lang.org/?version=stable&mode=debug&edition=2024&gist=99c818e83dfaa1204dc44cca93498bc1
 pub struct CommandIO {
-    pub stdin: Option<Stdio>,
-    pub stdout: PipeWriter,
-    pub stderr: PipeWriter,
+    /// Where standard in comes from, be it the previous command in the pipeline, a file or
+    /// the terminal. It isn't buffered, so a builtin only takes what it reads.
+    pub stdin: Stream,
+    /// Where standard out goes, be it a file, the pipe to the next command or the terminal.
+    /// What gets written to it goes straight there rather than through the shell.
+    pub stdout: Stream,
+    pub stderr: Stream,
+    /// The descriptors from 3 up, each to be moved onto the fd it is paired with. `None`
+    /// closes that fd.
+    pub other_descriptors: Vec<(RawFd, Option<OwnedFd>)>,
 }
 
 impl CommandIO {
-    pub fn new(stdin: Option<Stdio>, stdout: PipeWriter, stderr: PipeWriter) -> Self {
+    pub fn new(stdin: Stream, stdout: Stream, stderr: Stream) -> Self {
         Self {
             stdin,
             stdout,
             stderr,
+            other_descriptors: vec![],
+        }
+    }
+
+    /// Reads a line from standard in into `line`, newline included, and returns how many
+    /// bytes it read, which is 0 at the end of the input. It goes a byte at a time so that
+    /// whatever comes after the line is left for the next command to read.
+    pub fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        let mut bytes = vec![];
+        let mut byte = [0];
+
+        while self.stdin.read(&mut byte)? == 1 {
+            bytes.push(byte[0]);
+
+            if byte[0] == b'\n' {
+                break;
+            }
         }
+
+        line.push_str(&String::from_utf8_lossy(&bytes));
+        Ok(bytes.len())
     }
 }
@@ -4,33 +4,101 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum CustomError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
     #[error("Error: missing filename")]
     FilenameMissing,
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+    #[error("{0}: {1}")]
+    Arithmetic(String, String),
+    #[error("{0}: ambiguous redirect")]
+    AmbiguousRedirect(String),
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    #[error("{0}: cannot assign in this way")]
+    CannotAssign(String),
+    #[error("{0}: {1}")]
+    ParameterNotSet(String, String),
+    #[error("{0}: {}", describe_io_error(.1))]
+    Redirect(String, std::io::Error),
+    #[error("{0}: cannot overwrite existing file")]
+    CannotOverwrite(String),
+    #[error("{0}: Bad file descriptor")]
+    BadFileDescriptor(String),
+    #[error("no match: {0}")]
+    NoMatch(String),
+    #[error("{0}: redirection not supported")]
+    UnsupportedRedirect(String),
+    #[error("syntax error: unexpected end of input")]
+    UnexpectedEndOfInput,
+    #[error("unexpected end of input while looking for matching `{0}'")]
+    UnmatchedQuote(char),
+    #[error("here-document delimited by end of input (wanted `{0}')")]
+    UnterminatedHereDocument(String),
+}
+
+impl CustomError {
+    /// Whether the input was fine so far but stopped too early, meaning more lines should be
+    /// read before running it
+    pub fn is_incomplete_input(&self) -> bool {
+        matches!(
+            self,
+            Self::UnexpectedEndOfInput
+                | Self::UnmatchedQuote(_)
+                | Self::UnterminatedHereDocument(_)
+        )
+    }
+}
+
+/// The message for an io error without the `(os error 2)` on the end, the way bash shows it
+pub fn describe_io_error(error: &std::io::Error) -> String {
+    let message = error.to_string();
+
+    match message.find(" (os error") {
+        Some(os_error_index) => message[..os_error_index].to_owned(),
+        None => message,
+    }
 }
 
 /// Special thanks to Justus_Fluegel on Twitch for helping with errors
 /// https://play.rust-lang.org/?version=stable&mode=debug&edition=2024&gist=fb3dd1a5d6eec0899f16b45342652b80
 #[derive(Error, Debug)]
-pub struct ErrorExitCode(std::num::NonZero<i32>);
+pub struct ErrorExitCode(std::num::NonZero<i32>, Option<std::io::Error>);
 
 impl ErrorExitCode {
     pub fn new_const<const EXIT_CODE: i32>() -> Self {
-        const { Self(NonZero::new(EXIT_CODE).unwrap()) }
+        Self(const { NonZero::new(EXIT_CODE).unwrap() }, None)
+    }
+
+    /// The error from reading or writing that made the builtin fail, for the shell to show
+    pub fn io_error(&self) -> Option<&std::io::Error> {
+        self.1.as_ref()
+    }
+
+    /// `Ok` for an exit code of 0, otherwise the exit code as an error
+    pub fn from_exit_code(exit_code: i32) -> Result<(), Self> {
+        match NonZero::new(exit_code) {
+            Some(exit_code) => Err(Self(exit_code, None)),
+            None => Ok(()),
+        }
     }
 
-    pub fn new(code: i32) -> Self {
-        Self(NonZero::new(code).unwrap())
+    pub fn code(&self) -> i32 {
+        self.0.get()
     }
 }
 
+/// A builtin that can't read or write fails like bash's do, such as `echo` with standard
+/// out closed
 impl From<std::io::Error> for ErrorExitCode {
-    fn from(_error: std::io::Error) -> Self {
-        Self::new_const::<255>()
+    fn from(error: std::io::Error) -> Self {
+        Self(const { NonZero::new(1).unwrap() }, Some(error))
     }
 }
 
 impl Display for ErrorExitCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{}", self.0)
+        write!(f, "{}", self.0)
     }
 }
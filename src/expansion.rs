@@ -0,0 +1,276 @@
+use crate::{
+    Shell,
+    builtin_commands::shopt::ShellOptions,
+    errors::CustomError,
+    expansion::{parameter::ParameterExpansion, pattern::Pattern},
+    input_parser::{Word, WordPart},
+};
+use std::{
+    env,
+    ffi::{CStr, CString},
+};
+
+pub mod arithmetic;
+pub mod glob;
+pub mod parameter;
+pub mod pattern;
+
+/// Characters that separate fields when `IFS` isn't set
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Expands every word of a command, in order, into the final list of arguments
+pub fn expand_words(words: &[Word], shell: &mut Shell) -> Result<Vec<String>, CustomError> {
+    let mut arguments = vec![];
+
+    for word in words {
+        arguments.extend(expand_word(word, shell)?);
+    }
+
+    Ok(arguments)
+}
+
+/// Runs the expansions on a single word. Unquoted expansions are split into separate
+/// fields on `IFS` and then globbed, so one word can become zero or more arguments.
+pub fn expand_word(word: &Word, shell: &mut Shell) -> Result<Vec<String>, CustomError> {
+    let mut fields = Fields::default();
+
+    expand_parts(&word.parts, false, &mut fields, shell)?;
+
+    fields.finish_with_glob(&shell.options)
+}
+
+/// Expands a word without splitting it, for places that always take exactly one value such
+/// as the right hand side of `${NAME:=value}`
+pub fn expand_to_string(word: &Word, shell: &mut Shell) -> Result<String, CustomError> {
+    let mut fields = Fields::default();
+
+    expand_parts(&word.parts, true, &mut fields, shell)?;
+
+    Ok(fields.finish_text())
+}
+
+/// Expands a word into a pattern, escaping the quoted parts so that they match literally
+pub fn expand_to_pattern(word: &Word, shell: &mut Shell) -> Result<String, CustomError> {
+    let mut expanded = String::new();
+
+    for part in &word.parts {
+        let mut part_fields = Fields::default();
+
+        expand_parts(std::slice::from_ref(part), true, &mut part_fields, shell)?;
+
+        let text = part_fields.finish_text();
+
+        if part.is_quoted() {
+            expanded.push_str(&pattern::escape(&text));
+        } else {
+            expanded.push_str(&text);
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Expands `parts` into `fields`. Everything is treated as quoted when `quoted` is set, as
+/// it is for the parts of a default value inside a double quoted `"${NAME:-default}"`.
+fn expand_parts(
+    parts: &[WordPart],
+    quoted: bool,
+    fields: &mut Fields,
+    shell: &mut Shell,
+) -> Result<(), CustomError> {
+    for part in parts {
+        let quoted = quoted || part.is_quoted();
+
+        match part {
+            WordPart::Literal { text, .. } => fields.push_str(text, quoted),
+            WordPart::Parameter { parameter, .. } => match parameter.expand(shell)? {
+                ParameterExpansion::Text(value) if quoted => fields.push_str(&value, true),
+                ParameterExpansion::Text(value) => fields.push_split(&value),
+                ParameterExpansion::Word(word) => expand_operand(&word, quoted, fields, shell)?,
+                ParameterExpansion::Fields(elements) if quoted => {
+                    for (index, element) in elements.iter().enumerate() {
+                        if index > 0 {
+                            fields.end_field();
+                        }
+
+                        fields.push_str(element, true);
+                    }
+                }
+                ParameterExpansion::Fields(elements) => fields.push_split(&elements.join(" ")),
+            },
+            WordPart::Arithmetic { expression, .. } => {
+                let expression = expand_to_string(expression, shell)?;
+                fields.push_str(&arithmetic::evaluate(&expression)?.to_string(), quoted);
+            }
+            WordPart::Tilde { user } => match expand_tilde(user).filter(|_| !quoted) {
+                Some(directory) => fields.push_str(&directory, true),
+                None => fields.push_str(&format!("~{user}"), quoted),
+            },
+            WordPart::CommandSubstitution { command, .. } => {
+                let output = shell.command_substitution(command)?;
+
+                if quoted {
+                    fields.push_str(&output, true);
+                } else {
+                    fields.push_split(&output);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory a tilde prefix stands for: the home directory for `~`, someone else's for
+/// `~user`, and `$PWD` or `$OLDPWD` for `~+` and `~-`. `None` leaves the prefix as it is.
+fn expand_tilde(user: &str) -> Option<String> {
+    match user {
+        "" => env::var("HOME")
+            .ok()
+            .or_else(|| env::home_dir().map(|home| home.to_string_lossy().into_owned())),
+        "+" => env::var("PWD").ok(),
+        "-" => env::var("OLDPWD").ok(),
+        _ => {
+            let user = CString::new(user).ok()?;
+            let password_entry = unsafe { libc::getpwnam(user.as_ptr()) };
+
+            if password_entry.is_null() {
+                return None;
+            }
+
+            let home = unsafe { CStr::from_ptr((*password_entry).pw_dir) };
+
+            Some(home.to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// Expands the word a parameter was replaced with, like the default in `${NAME:-a b}`.
+/// Unlike the literal text of a command, its unquoted text is split into fields.
+fn expand_operand(
+    word: &Word,
+    quoted: bool,
+    fields: &mut Fields,
+    shell: &mut Shell,
+) -> Result<(), CustomError> {
+    for part in &word.parts {
+        match part {
+            WordPart::Literal {
+                text,
+                quoted: false,
+            } if !quoted => fields.push_split(text),
+            _ => expand_parts(std::slice::from_ref(part), quoted, fields, shell)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds up the fields that a word expands into
+#[derive(Default)]
+struct Fields {
+    fields: Vec<Field>,
+    current: Field,
+    /// Whether the current field exists, even if it is empty as with `""`
+    started: bool,
+    /// Whether the current field was just ended by IFS whitespace
+    delimited: bool,
+}
+
+/// One expanded field along with the pattern it is globbed with, where the characters that
+/// came from quoted text are escaped so they only match themselves
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+}
+
+impl Fields {
+    fn push_str(&mut self, text: &str, quoted: bool) {
+        self.current.text.push_str(text);
+
+        if quoted {
+            self.current.pattern.push_str(&pattern::escape(text));
+        } else {
+            self.current.pattern.push_str(text);
+        }
+
+        self.started = true;
+        self.delimited = false;
+    }
+
+    /// Adds the result of an unquoted expansion, starting a new field at every IFS character
+    fn push_split(&mut self, value: &str) {
+        let ifs = env::var("IFS").unwrap_or_else(|_| DEFAULT_IFS.to_owned());
+
+        for value_char in value.chars() {
+            if !ifs.contains(value_char) {
+                self.current.text.push(value_char);
+                self.current.pattern.push(value_char);
+                self.started = true;
+                self.delimited = false;
+            } else if value_char.is_whitespace() {
+                if self.started {
+                    self.end_field();
+                    self.delimited = true;
+                }
+            } else {
+                if self.started || !self.delimited {
+                    self.end_field();
+                }
+                self.delimited = false;
+            }
+        }
+    }
+
+    fn end_field(&mut self) {
+        self.fields.push(std::mem::take(&mut self.current));
+        self.started = false;
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        if self.started {
+            self.end_field();
+        }
+
+        self.fields
+    }
+
+    /// Finishes the fields and replaces each one that holds unquoted wildcards with the
+    /// paths it matches. What happens to a pattern that matches nothing depends on the
+    /// `nullglob` and `failglob` options, by default it is left as it was typed.
+    fn finish_with_glob(self, options: &ShellOptions) -> Result<Vec<String>, CustomError> {
+        let mut arguments = vec![];
+
+        for field in self.finish() {
+            if !Pattern::has_wildcards(&field.pattern, options.extglob) {
+                arguments.push(field.text);
+                continue;
+            }
+
+            let paths = glob::glob(&field.pattern, options);
+
+            if !paths.is_empty() {
+                arguments.extend(paths);
+            } else if options.failglob {
+                return Err(CustomError::NoMatch(field.text));
+            } else if !options.nullglob {
+                arguments.push(field.text);
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    /// Finishes the fields as a single string, where the fields of a quoted `"${NAME[@]}"`
+    /// are separated by spaces like bash does
+    fn finish_text(self) -> String {
+        let texts = self
+            .finish()
+            .into_iter()
+            .map(|field| field.text)
+            .collect::<Vec<_>>();
+
+        texts.join(" ")
+    }
+}
@@ -0,0 +1,533 @@
+use crate::errors::CustomError;
+use std::env;
+
+/// How deep variables whose values are themselves expressions may refer to each other
+const MAX_VARIABLE_DEPTH: usize = 64;
+
+/// Evaluates an integer expression as used by `$((...))` and `((...))`. Variables are read
+/// from and assigned to the environment.
+pub fn evaluate(expression: &str) -> Result<i64, CustomError> {
+    evaluate_at_depth(expression, 0)
+}
+
+fn evaluate_at_depth(expression: &str, depth: usize) -> Result<i64, CustomError> {
+    let error =
+        |message: &str| CustomError::Arithmetic(expression.trim().to_owned(), message.to_owned());
+
+    if depth > MAX_VARIABLE_DEPTH {
+        return Err(error("expression recursion level exceeded"));
+    }
+
+    let tokens = tokenize(expression).map_err(|message| error(&message))?;
+
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let parsed = parser.comma().map_err(|message| error(&message))?;
+
+    if let Some(token) = parser.tokens.get(parser.position) {
+        return Err(error(&format!(
+            "syntax error in expression (error token is \"{token}\")"
+        )));
+    }
+
+    parsed.evaluate(depth).map_err(|message| error(&message))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArithmeticToken {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for ArithmeticToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Operator(operator) => write!(f, "{operator}"),
+        }
+    }
+}
+
+/// Every operator, longest first so that `<<=` is found before `<<` and `<`
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<ArithmeticToken>, String> {
+    let mut tokens = vec![];
+    let mut rest = expression.trim_start();
+
+    while let Some(first_char) = rest.chars().next() {
+        if first_char.is_ascii_digit() {
+            let length = rest
+                .find(|number_char: char| {
+                    !(number_char.is_ascii_alphanumeric() || matches!(number_char, '#' | '@' | '_'))
+                })
+                .unwrap_or(rest.len());
+
+            tokens.push(ArithmeticToken::Number(parse_number(&rest[..length])?));
+            rest = &rest[length..];
+        } else if first_char.is_ascii_alphabetic() || first_char == '_' {
+            let length = rest
+                .find(|name_char: char| !(name_char.is_ascii_alphanumeric() || name_char == '_'))
+                .unwrap_or(rest.len());
+
+            tokens.push(ArithmeticToken::Name(rest[..length].to_owned()));
+            rest = &rest[length..];
+        } else if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator))
+        {
+            tokens.push(ArithmeticToken::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else {
+            return Err(format!(
+                "syntax error: operand expected (error token is \"{rest}\")"
+            ));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses decimal, `0x` hexadecimal, leading zero octal and `base#digits` numbers
+fn parse_number(number: &str) -> Result<i64, String> {
+    let invalid = || format!("value too great for base (error token is \"{number}\")");
+    let (base, digits) = if let Some((base, digits)) = number.split_once('#') {
+        let base = base.parse::<u32>().map_err(|_| invalid())?;
+
+        if !(2..=64).contains(&base) {
+            return Err(format!(
+                "invalid arithmetic base (error token is \"{number}\")"
+            ));
+        }
+
+        (base, digits)
+    } else if let Some(hex_digits) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        (16, hex_digits)
+    } else if number.len() > 1 && number.starts_with('0') {
+        (8, &number[1..])
+    } else {
+        (10, number)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+
+    for digit_char in digits.chars() {
+        let digit = match digit_char {
+            '0'..='9' => digit_char as u32 - '0' as u32,
+            'a'..='z' => digit_char as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => digit_char as u32 - 'A' as u32 + 10,
+            'A'..='Z' => digit_char as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+
+        if digit >= base {
+            return Err(invalid());
+        }
+
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+
+    Ok(value)
+}
+
+#[derive(Debug)]
+enum Expression {
+    Number(i64),
+    Variable(String),
+    /// `-x`, `+x`, `!x` and `~x`
+    Unary(&'static str, Box<Expression>),
+    /// `++x` and `--x`, or `x++` and `x--` when `postfix` is set
+    Increment {
+        name: String,
+        change: i64,
+        postfix: bool,
+    },
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+    /// `=` and the compound assignments like `+=`, which carry their binary operator
+    Assign {
+        name: String,
+        operator: Option<&'static str>,
+        value: Box<Expression>,
+    },
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+    Comma(Box<Expression>, Box<Expression>),
+}
+
+/// The binary operators from lowest to highest precedence. `&&`, `||`, the ternary and
+/// assignments are handled separately since they don't evaluate both sides.
+const BINARY_PRECEDENCE: [&[&str]; 8] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<ArithmeticToken>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn next_if_operator(&mut self, operators: &[&str]) -> Option<&'static str> {
+        let operator = self
+            .peek_operator()
+            .filter(|operator| operators.contains(operator))?;
+        self.position += 1;
+
+        Some(operator)
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<(), String> {
+        self.next_if_operator(&[operator])
+            .map(|_| ())
+            .ok_or_else(|| {
+                let found = self
+                    .tokens
+                    .get(self.position)
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                format!("syntax error: `{operator}' expected (error token is \"{found}\")")
+            })
+    }
+
+    fn comma(&mut self) -> Result<Expression, String> {
+        let mut expression = self.assignment()?;
+
+        while self.next_if_operator(&[","]).is_some() {
+            let next = self.assignment()?;
+            expression = Expression::Comma(Box::new(expression), Box::new(next));
+        }
+
+        Ok(expression)
+    }
+
+    fn assignment(&mut self) -> Result<Expression, String> {
+        if let Some(ArithmeticToken::Name(name)) = self.tokens.get(self.position).cloned()
+            && let Some(ArithmeticToken::Operator(operator)) = self.tokens.get(self.position + 1)
+            && ASSIGNMENT_OPERATORS.contains(operator)
+        {
+            let binary_operator = OPERATORS
+                .iter()
+                .find(|binary| operator.strip_suffix('=') == Some(**binary))
+                .copied();
+            self.position += 2;
+            let value = self.assignment()?;
+
+            return Ok(Expression::Assign {
+                name,
+                operator: binary_operator,
+                value: Box::new(value),
+            });
+        }
+
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expression, String> {
+        let condition = self.logical_or()?;
+
+        if self.next_if_operator(&["?"]).is_none() {
+            return Ok(condition);
+        }
+
+        let when_true = self.comma()?;
+        self.expect_operator(":")?;
+        let when_false = self.assignment()?;
+
+        Ok(Expression::Conditional(
+            Box::new(condition),
+            Box::new(when_true),
+            Box::new(when_false),
+        ))
+    }
+
+    fn logical_or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.logical_and()?;
+
+        while let Some(operator) = self.next_if_operator(&["||"]) {
+            let right = self.logical_and()?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn logical_and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.binary(0)?;
+
+        while let Some(operator) = self.next_if_operator(&["&&"]) {
+            let right = self.binary(0)?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(operators) = BINARY_PRECEDENCE.get(level) else {
+            return self.power();
+        };
+        let mut expression = self.binary(level + 1)?;
+
+        while let Some(operator) = self.next_if_operator(operators) {
+            let right = self.binary(level + 1)?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.unary()?;
+
+        if let Some(operator) = self.next_if_operator(&["**"]) {
+            let exponent = self.power()?;
+            return Ok(Expression::Binary(
+                operator,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if let Some(operator) = self.next_if_operator(&["++", "--"]) {
+            let Some(ArithmeticToken::Name(name)) = self.tokens.get(self.position).cloned() else {
+                return Err(format!("syntax error: `{operator}' needs a variable"));
+            };
+            self.position += 1;
+
+            return Ok(Expression::Increment {
+                name,
+                change: if operator == "++" { 1 } else { -1 },
+                postfix: false,
+            });
+        }
+
+        if let Some(operator) = self.next_if_operator(&["-", "+", "!", "~"]) {
+            let operand = self.unary()?;
+            return Ok(Expression::Unary(operator, Box::new(operand)));
+        }
+
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expression, String> {
+        let primary = self.primary()?;
+
+        if let Expression::Variable(name) = &primary
+            && let Some(operator) = self.next_if_operator(&["++", "--"])
+        {
+            return Ok(Expression::Increment {
+                name: name.clone(),
+                change: if operator == "++" { 1 } else { -1 },
+                postfix: true,
+            });
+        }
+
+        Ok(primary)
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        match token {
+            Some(ArithmeticToken::Number(number)) => Ok(Expression::Number(number)),
+            Some(ArithmeticToken::Name(name)) => Ok(Expression::Variable(name)),
+            Some(ArithmeticToken::Operator("(")) => {
+                let expression = self.comma()?;
+                self.expect_operator(")")?;
+                Ok(expression)
+            }
+            Some(token) => Err(format!(
+                "syntax error: operand expected (error token is \"{token}\")"
+            )),
+            None => Err("syntax error: operand expected".to_owned()),
+        }
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, depth: usize) -> Result<i64, String> {
+        match self {
+            Self::Number(number) => Ok(*number),
+            Self::Variable(name) => read_variable(name, depth),
+            Self::Unary(operator, operand) => {
+                let operand = operand.evaluate(depth)?;
+
+                Ok(match *operator {
+                    "-" => operand.wrapping_neg(),
+                    "!" => (operand == 0) as i64,
+                    "~" => !operand,
+                    _ => operand,
+                })
+            }
+            Self::Increment {
+                name,
+                change,
+                postfix,
+            } => {
+                let old_value = read_variable(name, depth)?;
+                let new_value = old_value.wrapping_add(*change);
+                write_variable(name, new_value);
+
+                Ok(if *postfix { old_value } else { new_value })
+            }
+            Self::Binary("&&", left, right) => {
+                Ok((left.evaluate(depth)? != 0 && right.evaluate(depth)? != 0) as i64)
+            }
+            Self::Binary("||", left, right) => {
+                Ok((left.evaluate(depth)? != 0 || right.evaluate(depth)? != 0) as i64)
+            }
+            Self::Binary(operator, left, right) => {
+                let left = left.evaluate(depth)?;
+                let right = right.evaluate(depth)?;
+
+                apply_binary(operator, left, right)
+            }
+            Self::Assign {
+                name,
+                operator,
+                value,
+            } => {
+                let value = value.evaluate(depth)?;
+                let new_value = match operator {
+                    Some(operator) => apply_binary(operator, read_variable(name, depth)?, value)?,
+                    None => value,
+                };
+                write_variable(name, new_value);
+
+                Ok(new_value)
+            }
+            Self::Conditional(condition, when_true, when_false) => {
+                if condition.evaluate(depth)? != 0 {
+                    when_true.evaluate(depth)
+                } else {
+                    when_false.evaluate(depth)
+                }
+            }
+            Self::Comma(first, second) => {
+                first.evaluate(depth)?;
+                second.evaluate(depth)
+            }
+        }
+    }
+}
+
+fn apply_binary(operator: &str, left: i64, right: i64) -> Result<i64, String> {
+    let value = match operator {
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("division by 0".to_owned()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err("exponent less than 0".to_owned()),
+        "**" => left.wrapping_pow(right.try_into().unwrap_or(u32::MAX)),
+        _ => unreachable!("{operator} is not a binary operator"),
+    };
+
+    Ok(value)
+}
+
+/// Reads a variable as a number. Unset and empty variables are 0, and anything else is
+/// evaluated as an expression of its own.
+fn read_variable(name: &str, depth: usize) -> Result<i64, String> {
+    let value = env::var(name).unwrap_or_default();
+
+    if value.trim().is_empty() {
+        return Ok(0);
+    }
+
+    evaluate_at_depth(&value, depth + 1).map_err(|error| error.to_string())
+}
+
+fn write_variable(name: &str, value: i64) {
+    unsafe { env::set_var(name, value.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(expression: &str) -> String {
+        match evaluate(expression) {
+            Err(CustomError::Arithmetic(_, message)) => message,
+            result => panic!("{expression} gave {result:?}"),
+        }
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4").unwrap(), 14);
+        assert_eq!(evaluate("(2 + 3) * 4").unwrap(), 20);
+        assert_eq!(evaluate("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(evaluate("-2 ** 2").unwrap(), 4);
+        assert_eq!(evaluate("1 < 2 && 3 | 4").unwrap(), 1);
+        assert_eq!(evaluate("1 - 2 - 3").unwrap(), -4);
+        assert_eq!(evaluate("0 ? 2 : 1 ? 3 : 4").unwrap(), 3);
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(error_message("1 / 0"), "division by 0");
+        assert_eq!(error_message("5 % (2 - 2)"), "division by 0");
+    }
+
+    #[test]
+    fn empty_expression_is_zero() {
+        assert_eq!(evaluate("").unwrap(), 0);
+    }
+}
@@ -0,0 +1,128 @@
+use super::pattern::Pattern;
+use crate::builtin_commands::shopt::ShellOptions;
+use std::{fs, path::Path};
+
+/// Finds the paths matching `pattern`, sorted. Each `/` separated component is matched
+/// against the entries of the directories found so far, so `src/*/mod.rs` only looks inside
+/// `src`. Names starting with `.` are only matched when the component itself starts with one,
+/// unless `dotglob` is on.
+pub fn glob(pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let (mut paths, pattern) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components = pattern.split('/').collect::<Vec<&str>>();
+
+    for (index, component) in components.iter().enumerate() {
+        let is_last = index == components.len() - 1;
+        let mut next_paths = vec![];
+
+        for path in &paths {
+            if options.globstar && *component == "**" {
+                // `**` matches any number of directories, including none at all
+                if !is_last || !path.is_empty() {
+                    next_paths.push(path.clone());
+                }
+
+                for descendant in descendants(path, options) {
+                    if is_last {
+                        next_paths.push(descendant);
+                    } else if Path::new(&descendant).is_dir() {
+                        next_paths.push(format!("{descendant}/"));
+                    }
+                }
+
+                continue;
+            }
+
+            let candidates = if Pattern::has_wildcards(component, options.extglob) {
+                matching_entries(path, component, options)
+            } else {
+                vec![format!("{path}{}", unescape(component))]
+            };
+
+            for candidate in candidates {
+                if is_last {
+                    if fs::symlink_metadata(&candidate).is_ok() {
+                        next_paths.push(candidate);
+                    }
+                } else if Path::new(&candidate).is_dir() {
+                    next_paths.push(format!("{candidate}/"));
+                }
+            }
+        }
+
+        paths = next_paths;
+
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Lists the entries of the directory at `directory` whose names match `component`
+fn matching_entries(directory: &str, component: &str, options: &ShellOptions) -> Vec<String> {
+    let pattern = Pattern::new(component, options.extglob);
+    let matches_hidden =
+        options.dotglob || component.starts_with('.') || component.starts_with("\\.");
+
+    read_names(directory)
+        .into_iter()
+        .filter(|name| matches_hidden || !name.starts_with('.'))
+        .filter(|name| pattern.matches(name))
+        .map(|name| format!("{directory}{name}"))
+        .collect()
+}
+
+/// Every file and directory below `directory`, for `**`. Symbolic links to directories
+/// aren't followed, so a link back up the tree can't loop forever.
+fn descendants(directory: &str, options: &ShellOptions) -> Vec<String> {
+    let mut found = vec![];
+
+    for name in read_names(directory) {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+
+        let path = format!("{directory}{name}");
+        let is_directory = fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+
+        found.push(path.clone());
+
+        if is_directory {
+            found.extend(descendants(&format!("{path}/"), options));
+        }
+    }
+
+    found
+}
+
+/// The names in the directory at `directory`, where an empty string means the current one
+fn read_names(directory: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(if directory.is_empty() { "." } else { directory }) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect()
+}
+
+/// Removes the backslashes that keep the characters of a pattern from being wildcards
+fn unescape(component: &str) -> String {
+    let mut unescaped = String::with_capacity(component.len());
+    let mut component_chars = component.chars();
+
+    while let Some(component_char) = component_chars.next() {
+        match component_char {
+            '\\' => unescaped.extend(component_chars.next()),
+            _ => unescaped.push(component_char),
+        }
+    }
+
+    unescaped
+}
@@ -0,0 +1,532 @@
+use super::{arithmetic, expand_to_pattern, expand_to_string, pattern::Pattern};
+use crate::{
+    Shell,
+    errors::CustomError,
+    input_parser::{Word, is_assignment_name, parse_word},
+};
+use std::{env, fmt::Display};
+
+/// A `$NAME` or `${NAME...}` reference to a shell parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub name: String,
+    /// The `[...]` after the name, for picking out elements of an array like `PIPESTATUS`
+    pub subscript: Option<Subscript>,
+    pub operation: Option<ParameterOperation>,
+    /// The text between the braces, kept for error messages
+    source: String,
+}
+
+/// Which elements of an array a parameter refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subscript {
+    /// `[@]` and `[*]`, every element. Inside double quotes `[@]` still expands to one field
+    /// per element, where `[*]` joins them into one.
+    All { separate: bool },
+    /// `[index]`, an arithmetic expression where a negative index counts back from the end
+    Index(Word),
+}
+
+/// The operators that can follow the name inside `${...}`. `check_null` is set for the
+/// forms with a colon, like `:-`, which treat an empty value the same as an unset one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParameterOperation {
+    /// `${#NAME}`
+    Length,
+    /// `${NAME:-word}`
+    Default { word: Word, check_null: bool },
+    /// `${NAME:=word}`
+    Assign { word: Word, check_null: bool },
+    /// `${NAME:?word}`
+    Error { word: Word, check_null: bool },
+    /// `${NAME:+word}`
+    Alternative { word: Word, check_null: bool },
+    /// `${NAME#pattern}` and `${NAME##pattern}`
+    RemovePrefix { pattern: Word, longest: bool },
+    /// `${NAME%pattern}` and `${NAME%%pattern}`
+    RemoveSuffix { pattern: Word, longest: bool },
+    /// `${NAME/pattern/replacement}` and its `//`, `/#` and `/%` forms
+    Replace {
+        pattern: Word,
+        replacement: Word,
+        mode: ReplaceMode,
+    },
+    /// `${NAME^pattern}`, `${NAME^^pattern}`, `${NAME,pattern}` and `${NAME,,pattern}`
+    ChangeCase {
+        pattern: Word,
+        uppercase: bool,
+        all: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceMode {
+    /// `/`, the first match
+    First,
+    /// `//`, every match
+    All,
+    /// `/#`, a match at the start
+    Prefix,
+    /// `/%`, a match at the end
+    Suffix,
+}
+
+/// What a parameter expands to. Defaults and alternatives expand to a word of their own so
+/// that any quoting inside them is kept.
+pub enum ParameterExpansion {
+    Text(String),
+    Word(Word),
+    /// Every element of an array from `${NAME[@]}`, each one a field of its own when quoted
+    Fields(Vec<String>),
+}
+
+impl Parameter {
+    pub fn new(name: String) -> Self {
+        Self {
+            source: name.clone(),
+            name,
+            subscript: None,
+            operation: None,
+        }
+    }
+
+    /// Parses the text between the braces of `${...}`
+    pub fn parse(braced: &str) -> Result<Self, CustomError> {
+        let bad_substitution = || CustomError::BadSubstitution(format!("${{{braced}}}"));
+
+        if let Some(after_hash) = braced.strip_prefix('#') {
+            let (name, rest) = after_hash.split_at(Self::name_length(after_hash));
+            let (subscript, rest) = Self::parse_subscript(name, rest)?;
+
+            if rest.is_empty() && Self::is_valid_name(name) {
+                return Ok(Self {
+                    name: name.to_owned(),
+                    subscript,
+                    operation: Some(ParameterOperation::Length),
+                    source: braced.to_owned(),
+                });
+            }
+        }
+
+        let name_length = Self::name_length(braced);
+        let (name, rest) = braced.split_at(name_length);
+
+        if !Self::is_valid_name(name) {
+            return Err(bad_substitution());
+        }
+
+        let (subscript, rest) = Self::parse_subscript(name, rest)?;
+
+        let check_null = rest.starts_with(':');
+        let without_colon = rest.strip_prefix(':').unwrap_or(rest);
+        let operation = match without_colon.chars().next() {
+            None if !check_null => None,
+            Some('-') => Some(ParameterOperation::Default {
+                word: parse_word(&without_colon[1..])?,
+                check_null,
+            }),
+            Some('=') => Some(ParameterOperation::Assign {
+                word: parse_word(&without_colon[1..])?,
+                check_null,
+            }),
+            Some('?') => Some(ParameterOperation::Error {
+                word: parse_word(&without_colon[1..])?,
+                check_null,
+            }),
+            Some('+') => Some(ParameterOperation::Alternative {
+                word: parse_word(&without_colon[1..])?,
+                check_null,
+            }),
+            _ if check_null => return Err(bad_substitution()),
+            Some('#') => {
+                let longest = rest.starts_with("##");
+                let pattern = if longest { &rest[2..] } else { &rest[1..] };
+
+                Some(ParameterOperation::RemovePrefix {
+                    pattern: parse_word(pattern)?,
+                    longest,
+                })
+            }
+            Some('%') => {
+                let longest = rest.starts_with("%%");
+                let pattern = if longest { &rest[2..] } else { &rest[1..] };
+
+                Some(ParameterOperation::RemoveSuffix {
+                    pattern: parse_word(pattern)?,
+                    longest,
+                })
+            }
+            Some('/') => {
+                let (mode, operand) = match rest[1..].chars().next() {
+                    Some('/') => (ReplaceMode::All, &rest[2..]),
+                    Some('#') => (ReplaceMode::Prefix, &rest[2..]),
+                    Some('%') => (ReplaceMode::Suffix, &rest[2..]),
+                    _ => (ReplaceMode::First, &rest[1..]),
+                };
+                let (pattern, replacement) = split_unquoted(operand, '/');
+
+                Some(ParameterOperation::Replace {
+                    pattern: parse_word(pattern)?,
+                    replacement: parse_word(replacement.unwrap_or_default())?,
+                    mode,
+                })
+            }
+            Some(case_char @ ('^' | ',')) => {
+                let all = rest[1..].starts_with(case_char);
+                let pattern = if all { &rest[2..] } else { &rest[1..] };
+
+                Some(ParameterOperation::ChangeCase {
+                    pattern: parse_word(pattern)?,
+                    uppercase: case_char == '^',
+                    all,
+                })
+            }
+            _ => return Err(bad_substitution()),
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            subscript,
+            operation,
+            source: braced.to_owned(),
+        })
+    }
+
+    /// Splits the `[...]` that can follow a name off the front of `rest`
+    fn parse_subscript<'a>(
+        name: &str,
+        rest: &'a str,
+    ) -> Result<(Option<Subscript>, &'a str), CustomError> {
+        let Some(after_bracket) = rest.strip_prefix('[') else {
+            return Ok((None, rest));
+        };
+        let Some(closing_index) = after_bracket.find(']') else {
+            return Ok((None, rest));
+        };
+
+        if !is_assignment_name(name) {
+            return Ok((None, rest));
+        }
+
+        let subscript = match &after_bracket[..closing_index] {
+            "@" => Subscript::All { separate: true },
+            "*" => Subscript::All { separate: false },
+            index => Subscript::Index(parse_word(index)?),
+        };
+
+        Ok((Some(subscript), &after_bracket[closing_index + 1..]))
+    }
+
+    /// Parameters like `$?` and `$$` whose name is a single punctuation character or digit
+    pub fn is_special(character: char) -> bool {
+        matches!(character, '?' | '$' | '!' | '#' | '@' | '*' | '-') || character.is_ascii_digit()
+    }
+
+    /// The length of the parameter name at the start of `braced`
+    fn name_length(braced: &str) -> usize {
+        match braced.chars().next() {
+            Some(first_char) if first_char.is_ascii_digit() => braced
+                .find(|name_char: char| !name_char.is_ascii_digit())
+                .unwrap_or(braced.len()),
+            Some(first_char) if Self::is_special(first_char) => 1,
+            _ => braced
+                .find(|name_char: char| !(name_char.is_ascii_alphanumeric() || name_char == '_'))
+                .unwrap_or(braced.len()),
+        }
+    }
+
+    fn is_valid_name(name: &str) -> bool {
+        let mut name_chars = name.chars();
+
+        match name_chars.next() {
+            Some(first_char) if first_char.is_ascii_digit() => {
+                name_chars.all(|name_char| name_char.is_ascii_digit())
+            }
+            Some(first_char) if Self::is_special(first_char) => name.len() == 1,
+            Some(first_char) if first_char.is_ascii_alphabetic() || first_char == '_' => {
+                name_chars.all(|name_char| name_char.is_ascii_alphanumeric() || name_char == '_')
+            }
+            _ => false,
+        }
+    }
+
+    fn is_assignable(&self) -> bool {
+        self.name
+            .starts_with(|first_char: char| first_char.is_ascii_alphabetic() || first_char == '_')
+    }
+
+    /// The current value of the parameter, or `None` if it is unset. Every element of an
+    /// array is joined with a space.
+    pub fn value(&self, shell: &mut Shell) -> Result<Option<String>, CustomError> {
+        let value = match &self.subscript {
+            None => self.elements(shell).into_iter().next(),
+            Some(Subscript::All { .. }) => {
+                let elements = self.elements(shell);
+                (!elements.is_empty()).then(|| elements.join(" "))
+            }
+            Some(Subscript::Index(index)) => {
+                let index = arithmetic::evaluate(&expand_to_string(index, shell)?)?;
+                let mut elements = self.elements(shell);
+                let index = if index < 0 {
+                    usize::try_from(elements.len() as i64 + index).ok()
+                } else {
+                    usize::try_from(index).ok()
+                };
+
+                index
+                    .filter(|index| *index < elements.len())
+                    .map(|index| elements.swap_remove(index))
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// The elements of the parameter as an array. `PIPESTATUS` is the only real array, any
+    /// other parameter that is set is an array of just its value, like it is in bash.
+    fn elements(&self, shell: &Shell) -> Vec<String> {
+        let value = match self.name.as_str() {
+            "PIPESTATUS" => return shell.pipe_status.iter().map(ToString::to_string).collect(),
+            "?" => Some(env::var("?").unwrap_or_else(|_| "0".to_owned())),
+            "$" => Some(std::process::id().to_string()),
+            "0" => env::args().next(),
+            "#" => Some("0".to_owned()),
+            "-" => Some(String::new()),
+            "!" => shell.jobs.last_background_pid.map(|pid| pid.to_string()),
+            "@" | "*" => None,
+            name if name.starts_with(|first_char: char| first_char.is_ascii_digit()) => None,
+            name => env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+        };
+
+        value.into_iter().collect()
+    }
+
+    pub fn expand(&self, shell: &mut Shell) -> Result<ParameterExpansion, CustomError> {
+        let value = self.value(shell)?;
+        let Some(operation) = &self.operation else {
+            if self.subscript == Some(Subscript::All { separate: true }) {
+                return Ok(ParameterExpansion::Fields(self.elements(shell)));
+            }
+
+            return Ok(ParameterExpansion::Text(value.unwrap_or_default()));
+        };
+        let is_unset = |check_null: bool| {
+            value
+                .as_ref()
+                .is_none_or(|value| check_null && value.is_empty())
+        };
+
+        let expansion = match operation {
+            ParameterOperation::Length => {
+                let length = match self.subscript {
+                    Some(Subscript::All { .. }) => self.elements(shell).len(),
+                    _ => value.unwrap_or_default().chars().count(),
+                };
+                ParameterExpansion::Text(length.to_string())
+            }
+            ParameterOperation::Default { word, check_null } => {
+                if is_unset(*check_null) {
+                    ParameterExpansion::Word(word.clone())
+                } else {
+                    ParameterExpansion::Text(value.unwrap_or_default())
+                }
+            }
+            ParameterOperation::Assign { word, check_null } => {
+                if is_unset(*check_null) {
+                    if !self.is_assignable() {
+                        return Err(CustomError::CannotAssign(format!("${}", self.name)));
+                    }
+
+                    let assigned_value = expand_to_string(word, shell)?;
+                    unsafe { env::set_var(&self.name, &assigned_value) }
+                    ParameterExpansion::Text(assigned_value)
+                } else {
+                    ParameterExpansion::Text(value.unwrap_or_default())
+                }
+            }
+            ParameterOperation::Error { word, check_null } => {
+                if is_unset(*check_null) {
+                    let mut message = expand_to_string(word, shell)?;
+
+                    if message.is_empty() {
+                        message = "parameter null or not set".to_owned();
+                    }
+
+                    return Err(CustomError::ParameterNotSet(self.name.clone(), message));
+                }
+
+                ParameterExpansion::Text(value.unwrap_or_default())
+            }
+            ParameterOperation::Alternative { word, check_null } => {
+                if is_unset(*check_null) {
+                    ParameterExpansion::Text(String::new())
+                } else {
+                    ParameterExpansion::Word(word.clone())
+                }
+            }
+            ParameterOperation::RemovePrefix { pattern, longest } => {
+                let value = value.unwrap_or_default();
+                let pattern =
+                    Pattern::new(&expand_to_pattern(pattern, shell)?, shell.options.extglob);
+
+                ParameterExpansion::Text(remove_prefix(&value, &pattern, *longest))
+            }
+            ParameterOperation::RemoveSuffix { pattern, longest } => {
+                let value = value.unwrap_or_default();
+                let pattern =
+                    Pattern::new(&expand_to_pattern(pattern, shell)?, shell.options.extglob);
+
+                ParameterExpansion::Text(remove_suffix(&value, &pattern, *longest))
+            }
+            ParameterOperation::Replace {
+                pattern,
+                replacement,
+                mode,
+            } => {
+                let value = value.unwrap_or_default();
+                let pattern =
+                    Pattern::new(&expand_to_pattern(pattern, shell)?, shell.options.extglob);
+                let replacement = expand_to_string(replacement, shell)?;
+
+                ParameterExpansion::Text(replace(&value, &pattern, &replacement, *mode))
+            }
+            ParameterOperation::ChangeCase {
+                pattern,
+                uppercase,
+                all,
+            } => {
+                let value = value.unwrap_or_default();
+                let pattern = expand_to_pattern(pattern, shell)?;
+                let pattern = Pattern::new(
+                    if pattern.is_empty() { "?" } else { &pattern },
+                    shell.options.extglob,
+                );
+
+                ParameterExpansion::Text(change_case(&value, &pattern, *uppercase, *all))
+            }
+        };
+
+        Ok(expansion)
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "${{{}}}", self.source)
+    }
+}
+
+/// Splits `text` at the first `delimiter` that isn't escaped or quoted
+fn split_unquoted(text: &str, delimiter: char) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (index, text_char) in text.char_indices() {
+        match (text_char, quote) {
+            _ if escaped => escaped = false,
+            ('\\', Some('\'')) => (),
+            ('\\', _) => escaped = true,
+            (_, Some(quote_char)) if text_char == quote_char => quote = None,
+            (_, Some(_)) => (),
+            ('\'' | '"', None) => quote = Some(text_char),
+            _ if text_char == delimiter => {
+                return (&text[..index], Some(&text[index + text_char.len_utf8()..]));
+            }
+            _ => (),
+        }
+    }
+
+    (text, None)
+}
+
+/// The byte offsets of every character boundary in `text`, including its end
+fn char_boundaries(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut boundaries = char_boundaries(value);
+
+    if longest {
+        boundaries.reverse();
+    }
+
+    boundaries
+        .into_iter()
+        .find(|end| pattern.matches(&value[..*end]))
+        .map(|end| value[end..].to_owned())
+        .unwrap_or_else(|| value.to_owned())
+}
+
+fn remove_suffix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut boundaries = char_boundaries(value);
+
+    if !longest {
+        boundaries.reverse();
+    }
+
+    boundaries
+        .into_iter()
+        .find(|start| pattern.matches(&value[*start..]))
+        .map(|start| value[..start].to_owned())
+        .unwrap_or_else(|| value.to_owned())
+}
+
+fn replace(value: &str, pattern: &Pattern, replacement: &str, mode: ReplaceMode) -> String {
+    let boundaries = char_boundaries(value);
+    let mut result = String::new();
+    let mut copied_up_to = 0;
+    let mut start_position = 0;
+
+    while start_position < boundaries.len() {
+        let start = boundaries[start_position];
+        let found_end = match mode {
+            ReplaceMode::Prefix if start != 0 => None,
+            ReplaceMode::Suffix => pattern.matches(&value[start..]).then_some(value.len()),
+            _ => boundaries[start_position..]
+                .iter()
+                .rev()
+                .find(|end| pattern.matches(&value[start..**end]))
+                .copied(),
+        };
+
+        match found_end {
+            Some(end) if end > start || !matches!(mode, ReplaceMode::All) => {
+                result.push_str(&value[copied_up_to..start]);
+                result.push_str(replacement);
+                copied_up_to = end;
+
+                if !matches!(mode, ReplaceMode::All) {
+                    break;
+                }
+
+                start_position = boundaries.partition_point(|boundary| *boundary < end);
+            }
+            _ => start_position += 1,
+        }
+    }
+
+    result.push_str(&value[copied_up_to..]);
+    result
+}
+
+fn change_case(value: &str, pattern: &Pattern, uppercase: bool, all: bool) -> String {
+    let mut result = String::with_capacity(value.len());
+
+    for (index, value_char) in value.chars().enumerate() {
+        let should_change = (all || index == 0) && pattern.matches(&value_char.to_string());
+
+        if !should_change {
+            result.push(value_char);
+        } else if uppercase {
+            result.extend(value_char.to_uppercase());
+        } else {
+            result.extend(value_char.to_lowercase());
+        }
+    }
+
+    result
+}
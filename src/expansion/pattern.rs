@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+/// A shell pattern such as `*.rs` or `[a-z]?`. A backslash makes the character after it
+/// match literally, which is how quoted parts of a pattern are kept from matching as
+/// wildcards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<PatternToken>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PatternToken {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyString,
+    /// `[abc]`, `[a-z]`, `[!abc]` or `[^abc]`
+    Bracket {
+        negated: bool,
+        items: Vec<BracketItem>,
+    },
+    /// An extglob group such as `+(a|b)`
+    Group {
+        kind: GroupKind,
+        alternatives: Vec<Pattern>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupKind {
+    /// `?(...)`
+    ZeroOrOne,
+    /// `*(...)`
+    ZeroOrMore,
+    /// `+(...)`
+    OneOrMore,
+    /// `@(...)`
+    ExactlyOne,
+    /// `!(...)`, anything the alternatives don't match
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BracketItem {
+    Char(char),
+    Range(char, char),
+    /// `[:alpha:]` and friends
+    Class(String),
+}
+
+impl Pattern {
+    /// Parses `pattern`, also understanding the `?(...)`, `*(...)`, `+(...)`, `@(...)` and
+    /// `!(...)` groups when `extglob` is set
+    pub fn new(pattern: &str, extglob: bool) -> Self {
+        let chars = pattern.chars().collect::<Vec<char>>();
+
+        Self::from_chars(&chars, extglob)
+    }
+
+    fn from_chars(chars: &[char], extglob: bool) -> Self {
+        let mut tokens = vec![];
+        let mut index = 0;
+
+        while index < chars.len() {
+            let token = match chars[index] {
+                '\\' if index + 1 < chars.len() => {
+                    index += 1;
+                    PatternToken::Literal(chars[index])
+                }
+                group_char @ ('?' | '*' | '+' | '@' | '!')
+                    if extglob
+                        && chars.get(index + 1) == Some(&'(')
+                        && let Some((token, end_index)) =
+                            Self::parse_group(group_char, chars, index + 2) =>
+                {
+                    index = end_index;
+                    token
+                }
+                '?' => PatternToken::AnyChar,
+                '*' => PatternToken::AnyString,
+                '[' => match Self::parse_bracket(chars, index + 1) {
+                    Some((token, end_index)) => {
+                        index = end_index;
+                        token
+                    }
+                    None => PatternToken::Literal('['),
+                },
+                literal_char => PatternToken::Literal(literal_char),
+            };
+
+            tokens.push(token);
+            index += 1;
+        }
+
+        Self { tokens }
+    }
+
+    /// Whether `pattern` contains any unescaped wildcards, meaning it can match something
+    /// other than its own text
+    pub fn has_wildcards(pattern: &str, extglob: bool) -> bool {
+        Self::new(pattern, extglob)
+            .tokens
+            .iter()
+            .any(|token| !matches!(token, PatternToken::Literal(_)))
+    }
+
+    /// Parses the `|` separated patterns of an extglob group starting just after its `(`,
+    /// returning the token and the index of the closing `)`
+    fn parse_group(
+        group_char: char,
+        chars: &[char],
+        start: usize,
+    ) -> Option<(PatternToken, usize)> {
+        let mut alternatives = vec![];
+        let mut alternative_start = start;
+        let mut depth = 0;
+        let mut index = start;
+
+        while let Some(&group_char_at) = chars.get(index) {
+            match group_char_at {
+                '\\' => index += 1,
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                '|' | ')' if depth == 0 => {
+                    alternatives.push(Self::from_chars(&chars[alternative_start..index], true));
+                    alternative_start = index + 1;
+
+                    if group_char_at == ')' {
+                        let kind = match group_char {
+                            '?' => GroupKind::ZeroOrOne,
+                            '*' => GroupKind::ZeroOrMore,
+                            '+' => GroupKind::OneOrMore,
+                            '@' => GroupKind::ExactlyOne,
+                            _ => GroupKind::Not,
+                        };
+
+                        return Some((PatternToken::Group { kind, alternatives }, index));
+                    }
+                }
+                _ => (),
+            }
+
+            index += 1;
+        }
+
+        None
+    }
+
+    /// Parses a bracket expression starting just after its `[`, returning the token and the
+    /// index of the closing `]`. Returns `None` when the bracket is never closed, in which case
+    /// the `[` is matched literally.
+    fn parse_bracket(chars: &[char], start: usize) -> Option<(PatternToken, usize)> {
+        let mut index = start;
+        let negated = matches!(chars.get(index), Some('!' | '^'));
+        let mut items = vec![];
+
+        if negated {
+            index += 1;
+        }
+
+        let first_item_index = index;
+
+        while let Some(&bracket_char) = chars.get(index) {
+            if bracket_char == ']' && index != first_item_index {
+                return Some((PatternToken::Bracket { negated, items }, index));
+            }
+
+            if bracket_char == '['
+                && chars.get(index + 1) == Some(&':')
+                && let Some(class_length) = chars[index + 2..]
+                    .windows(2)
+                    .position(|window| window == [':', ']'])
+            {
+                let class = chars[index + 2..index + 2 + class_length].iter().collect();
+                items.push(BracketItem::Class(class));
+                index += class_length + 4;
+                continue;
+            }
+
+            let start_char = if bracket_char == '\\' && index + 1 < chars.len() {
+                index += 1;
+                chars[index]
+            } else {
+                bracket_char
+            };
+
+            if chars.get(index + 1) == Some(&'-')
+                && let Some(&end_char) = chars.get(index + 2)
+                && end_char != ']'
+            {
+                items.push(BracketItem::Range(start_char, end_char));
+                index += 3;
+            } else {
+                items.push(BracketItem::Char(start_char));
+                index += 1;
+            }
+        }
+
+        None
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<char>>();
+
+        self.matches_chars(&text)
+    }
+
+    fn matches_chars(&self, text: &[char]) -> bool {
+        if self
+            .tokens
+            .iter()
+            .any(|token| matches!(token, PatternToken::Group { .. }))
+        {
+            matches_tokens(&self.tokens, text)
+        } else {
+            self.matches_without_groups(text)
+        }
+    }
+
+    /// Matches using the usual backtracking over the most recent `*`
+    fn matches_without_groups(&self, text: &[char]) -> bool {
+        let mut token_index = 0;
+        let mut text_index = 0;
+        let mut last_star: Option<(usize, usize)> = None;
+
+        while text_index < text.len() {
+            match self.tokens.get(token_index) {
+                Some(PatternToken::AnyString) => {
+                    last_star = Some((token_index, text_index));
+                    token_index += 1;
+                    continue;
+                }
+                Some(token) if token.matches_char(text[text_index]) => {
+                    token_index += 1;
+                    text_index += 1;
+                    continue;
+                }
+                _ => (),
+            }
+
+            let Some((star_token_index, star_text_index)) = last_star else {
+                return false;
+            };
+
+            token_index = star_token_index + 1;
+            text_index = star_text_index + 1;
+            last_star = Some((star_token_index, text_index));
+        }
+
+        self.tokens[token_index..]
+            .iter()
+            .all(|token| matches!(token, PatternToken::AnyString))
+    }
+}
+
+/// Matches `text` by trying every way of splitting it between the tokens, which extglob groups
+/// need since they can match text of any length
+fn matches_tokens(tokens: &[PatternToken], text: &[char]) -> bool {
+    matches_tokens_from(tokens, text, 0, 0, &mut HashMap::new())
+}
+
+/// Whether the tokens from `token_index` on match the text from `text_offset` on. Each pair
+/// of those is only worked out once, otherwise patterns like `*a*a*a*b` take exponential
+/// time on text that almost matches.
+fn matches_tokens_from(
+    tokens: &[PatternToken],
+    text: &[char],
+    token_index: usize,
+    text_offset: usize,
+    memo: &mut HashMap<(usize, usize), bool>,
+) -> bool {
+    if let Some(&is_match) = memo.get(&(token_index, text_offset)) {
+        return is_match;
+    }
+
+    let is_match = match tokens.get(token_index) {
+        None => text_offset == text.len(),
+        Some(PatternToken::AnyString) => (text_offset..=text.len())
+            .any(|start| matches_tokens_from(tokens, text, token_index + 1, start, memo)),
+        Some(PatternToken::Group { kind, alternatives }) => (text_offset..=text.len()).any(|end| {
+            kind.matches(alternatives, &text[text_offset..end])
+                && matches_tokens_from(tokens, text, token_index + 1, end, memo)
+        }),
+        Some(token) => text.get(text_offset).is_some_and(|&text_char| {
+            token.matches_char(text_char)
+                && matches_tokens_from(tokens, text, token_index + 1, text_offset + 1, memo)
+        }),
+    };
+
+    memo.insert((token_index, text_offset), is_match);
+    is_match
+}
+
+impl GroupKind {
+    fn matches(&self, alternatives: &[Pattern], text: &[char]) -> bool {
+        let matches_one = |text: &[char]| {
+            alternatives
+                .iter()
+                .any(|alternative| alternative.matches_chars(text))
+        };
+
+        match self {
+            Self::ZeroOrOne => text.is_empty() || matches_one(text),
+            Self::ZeroOrMore => text.is_empty() || matches_repeated(alternatives, text),
+            Self::OneOrMore => !text.is_empty() && matches_repeated(alternatives, text),
+            Self::ExactlyOne => matches_one(text),
+            Self::Not => !matches_one(text),
+        }
+    }
+}
+
+/// Whether `text` can be split into one or more pieces that each match one of `alternatives`
+fn matches_repeated(alternatives: &[Pattern], text: &[char]) -> bool {
+    matches_repeated_from(alternatives, text, 0, &mut HashMap::new())
+}
+
+/// Whether the text from `text_offset` on can be split up like that, remembering the answer
+/// for each offset so no piece is tried twice
+fn matches_repeated_from(
+    alternatives: &[Pattern],
+    text: &[char],
+    text_offset: usize,
+    memo: &mut HashMap<usize, bool>,
+) -> bool {
+    if text_offset == text.len() {
+        return true;
+    }
+
+    if let Some(&is_match) = memo.get(&text_offset) {
+        return is_match;
+    }
+
+    let is_match = (text_offset + 1..=text.len()).any(|end| {
+        alternatives
+            .iter()
+            .any(|alternative| alternative.matches_chars(&text[text_offset..end]))
+            && matches_repeated_from(alternatives, text, end, memo)
+    });
+
+    memo.insert(text_offset, is_match);
+    is_match
+}
+
+impl PatternToken {
+    fn matches_char(&self, text_char: char) -> bool {
+        match self {
+            Self::Literal(literal_char) => *literal_char == text_char,
+            Self::AnyChar => true,
+            Self::AnyString | Self::Group { .. } => false,
+            Self::Bracket { negated, items } => {
+                items.iter().any(|item| item.matches_char(text_char)) != *negated
+            }
+        }
+    }
+}
+
+impl BracketItem {
+    fn matches_char(&self, text_char: char) -> bool {
+        match self {
+            Self::Char(item_char) => *item_char == text_char,
+            Self::Range(start, end) => (*start..=*end).contains(&text_char),
+            Self::Class(class) => match class.as_str() {
+                "alnum" => text_char.is_alphanumeric(),
+                "alpha" => text_char.is_alphabetic(),
+                "blank" => matches!(text_char, ' ' | '\t'),
+                "cntrl" => text_char.is_control(),
+                "digit" => text_char.is_ascii_digit(),
+                "graph" => text_char.is_ascii_graphic(),
+                "lower" => text_char.is_lowercase(),
+                "print" => text_char.is_ascii_graphic() || text_char == ' ',
+                "punct" => text_char.is_ascii_punctuation(),
+                "space" => text_char.is_whitespace(),
+                "upper" => text_char.is_uppercase(),
+                "xdigit" => text_char.is_ascii_hexdigit(),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Escapes `text` so that every character in it matches literally
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for text_char in text.chars() {
+        if matches!(
+            text_char,
+            '\\' | '*' | '?' | '[' | ']' | '+' | '@' | '!' | '(' | ')' | '|'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(text_char);
+    }
+
+    escaped
+}
@@ -1,39 +1,41 @@
 use crate::{
     builtin_commands::{BuiltinCommand, history::History},
+    command::parse_user_input,
     utilities::{
         are_all_items_same_length, calculate_longest_common_prefix, find_executable_files, get_path,
     },
 };
 use anyhow::Result;
 use console::{Key, Term};
-use std::{
-    io::Write,
-    iter::{Cycle, Map},
-    vec::IntoIter,
-};
+use std::{io::Write, iter::Cycle, vec::IntoIter};
 
 pub struct UserInput {
     ps1: &'static str,
+    /// Shown instead of `ps1` while the rest of an incomplete command is being typed
+    ps2: &'static str,
     term: Term,
 }
 
 impl UserInput {
-    pub fn new(ps1: &'static str) -> Self {
+    pub fn new(ps1: &'static str, ps2: &'static str) -> Self {
         let term = Term::stdout();
 
-        Self { ps1, term }
+        Self { ps1, ps2, term }
     }
 
-    pub fn readline(&mut self, history: &mut History) -> Result<String> {
+    /// Reads a command, which goes on over more lines for as long as it is incomplete.
+    /// `extglob` decides whether an unclosed group like `@(a` is one of those.
+    pub fn readline(&mut self, history: &mut History, extglob: bool) -> Result<String> {
         let mut in_command = true;
         let mut user_input = String::new();
         let mut autocomplete_bell = false;
-        let mut autocomplete_lcp: Option<Cycle<Map<IntoIter<(usize, String)>, _>>> = None;
+        let mut autocomplete_lcp: Option<Cycle<IntoIter<String>>> = None;
 
         self.print_prompt()?;
 
         loop {
-            let key_code = self.term.read_key()?;
+            // raw, so that `Ctrl-C` comes through as a key instead of as a signal
+            let key_code = self.term.read_key_raw()?;
 
             match key_code {
                 Key::Unknown => todo!(),
@@ -44,26 +46,37 @@ impl UserInput {
                     let Some(previous_prompt) = history.get_previous_prompt() else {
                         continue;
                     };
-                    self.term.clear_line()?;
-                    self.print_prompt()?;
+                    self.replace_input(&user_input, previous_prompt)?;
                     user_input = previous_prompt.to_owned();
-
-                    self.term.write(previous_prompt.as_bytes())?;
                 }
                 Key::ArrowDown => {
                     let next_prompt = history.get_next_prompt().unwrap_or_default();
 
-                    self.term.clear_line()?;
-                    self.print_prompt()?;
+                    self.replace_input(&user_input, next_prompt)?;
                     user_input = next_prompt.to_owned();
-                    self.term.write(next_prompt.as_bytes())?;
                 }
                 Key::Enter => {
                     self.term.write_line("")?;
+
+                    if parse_user_input(user_input.clone(), extglob)
+                        .is_err_and(|error| error.is_incomplete_input())
+                    {
+                        user_input.push('\n');
+                        self.term.flush()?;
+                        write!(&self.term, "{}", self.ps2)?;
+                        self.term.flush()?;
+                        continue;
+                    }
+
                     history.reset_lookback();
                     return Ok(user_input);
                 }
                 Key::Backspace => {
+                    // lines that have already been entered can't be edited
+                    if user_input.ends_with('\n') {
+                        continue;
+                    }
+
                     self.term.clear_chars(1)?;
                     user_input.pop();
                 }
@@ -74,12 +87,12 @@ impl UserInput {
                         continue;
                     }
 
-                    if let Some(matching_words) = autocomplete_lcp.as_mut() {
-                        if let Some(word) = matching_words.next() {
-                            user_input = String::from(word);
-                            self.rewrite_line(&user_input)?;
-                            continue;
-                        }
+                    if let Some(matching_words) = autocomplete_lcp.as_mut()
+                        && let Some(word) = matching_words.next()
+                    {
+                        user_input = word;
+                        self.rewrite_line(&user_input)?;
+                        continue;
                     }
 
                     if let Some(completed_command) = self.autocomplete_one_builtin(&user_input) {
@@ -124,11 +137,13 @@ impl UserInput {
                         let mut possible_commands_with_lcp =
                             calculate_longest_common_prefix(&user_input, &possible_commands);
 
-                        possible_commands_with_lcp.sort_by(|a, b| a.0.cmp(&b.0));
+                        possible_commands_with_lcp.sort_by_key(|(lcp, _)| *lcp);
                         autocomplete_lcp = Some(
                             possible_commands_with_lcp
                                 .into_iter()
                                 .map(|(_, word)| word)
+                                .collect::<Vec<String>>()
+                                .into_iter()
                                 .cycle(),
                         );
 
@@ -155,7 +170,17 @@ impl UserInput {
 
                     autocomplete_lcp = None;
                 }
-                Key::CtrlC => todo!(),
+                Key::CtrlC => {
+                    // like bash, whatever was typed so far is dropped for a fresh prompt
+                    user_input.clear();
+                    in_command = true;
+                    autocomplete_bell = false;
+                    autocomplete_lcp = None;
+                    history.reset_lookback();
+
+                    self.term.write_line("")?;
+                    self.print_prompt()?;
+                }
                 _ => (),
             }
 
@@ -188,10 +213,26 @@ impl UserInput {
         Ok(())
     }
 
+    /// Swaps what was typed for a command from the history. Either of them can go over
+    /// several lines, each one after the first getting `ps2` like when it was typed.
+    fn replace_input(&self, old_input: &str, new_input: &str) -> Result<()> {
+        self.term.clear_line()?;
+        self.term
+            .clear_last_lines(old_input.matches('\n').count())?;
+        self.print_prompt()?;
+        write!(
+            &self.term,
+            "{}",
+            new_input.replace('\n', &format!("\n{}", self.ps2))
+        )?;
+
+        Ok(())
+    }
+
     fn get_possible_commands(&self, user_input: &str) -> Result<Vec<String>> {
         let matching_builtins = BuiltinCommand::matches(user_input);
 
-        if matching_builtins.len() > 0 {
+        if !matching_builtins.is_empty() {
             return Ok(matching_builtins);
         }
 
@@ -1,126 +1,1414 @@
-use std::collections::VecDeque;
+use crate::{errors::CustomError, expansion::parameter::Parameter};
+use std::{collections::VecDeque, fmt::Display, iter::Peekable, str::Chars};
+
+/// A piece of parsed user input. Operators are recognized with or without whitespace
+/// around them, but anything that was quoted or escaped is always a word, so `echo ">"`
+/// prints a `>` instead of redirecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(Word),
+    Operator(Operator),
+    /// The `(( expression ))` command
+    Arithmetic(Word),
+}
+
+/// A word as it was typed, split into the parts that expand differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    /// Plain text. `quoted` records whether it was inside quotes or escaped
+    Literal { text: String, quoted: bool },
+    /// `$NAME` or `${NAME}`
+    Parameter { parameter: Parameter, quoted: bool },
+    /// `$(command)` or `` `command` ``
+    CommandSubstitution { command: String, quoted: bool },
+    /// `$((expression))`
+    Arithmetic { expression: Word, quoted: bool },
+    /// `~`, `~user`, `~+` or `~-` at the start of a word, holding the text after the `~`
+    Tilde { user: String },
+}
+
+impl Word {
+    /// Whether any part of the word was quoted or escaped
+    pub fn is_quoted(&self) -> bool {
+        self.parts.iter().any(WordPart::is_quoted)
+    }
+}
+
+impl Word {
+    /// Finds the tilde prefixes of the word: an unquoted `~` at its start, up to the first
+    /// `/`. In an assignment like `PATH=~/bin:~/.local/bin` they can also follow the `=` or
+    /// a `:`, and end at the next `:`. A prefix with anything quoted or expanded in it is
+    /// left alone.
+    fn with_tilde_prefixes(self) -> Self {
+        let is_assignment = matches!(
+            self.parts.first(),
+            Some(WordPart::Literal { text, quoted: false })
+                if text.split_once('=').is_some_and(|(name, _)| is_assignment_name(name))
+        );
+        let part_count = self.parts.len();
+        let mut parts = vec![];
+        let mut seen_equals = false;
+
+        for (index, part) in self.parts.into_iter().enumerate() {
+            let WordPart::Literal {
+                text,
+                quoted: false,
+            } = part
+            else {
+                parts.push(part);
+                continue;
+            };
+
+            let text_chars = text.chars().collect::<Vec<char>>();
+            let mut literal = String::new();
+            let mut can_start_prefix = index == 0;
+            let mut char_index = 0;
+
+            while char_index < text_chars.len() {
+                let text_char = text_chars[char_index];
+
+                if can_start_prefix && text_char == '~' {
+                    let prefix_end = text_chars[char_index + 1..]
+                        .iter()
+                        .position(|&end_char| end_char == '/' || (is_assignment && end_char == ':'))
+                        .map(|position| char_index + 1 + position)
+                        .or((index == part_count - 1).then_some(text_chars.len()));
+
+                    if let Some(prefix_end) = prefix_end {
+                        if !literal.is_empty() {
+                            parts.push(WordPart::Literal {
+                                text: std::mem::take(&mut literal),
+                                quoted: false,
+                            });
+                        }
+
+                        parts.push(WordPart::Tilde {
+                            user: text_chars[char_index + 1..prefix_end].iter().collect(),
+                        });
+                        char_index = prefix_end;
+                        can_start_prefix = false;
+                        continue;
+                    }
+                }
+
+                can_start_prefix =
+                    is_assignment && (text_char == ':' || (text_char == '=' && !seen_equals));
+                seen_equals |= text_char == '=';
+                literal.push(text_char);
+                char_index += 1;
+            }
+
+            if !literal.is_empty() {
+                parts.push(WordPart::Literal {
+                    text: literal,
+                    quoted: false,
+                });
+            }
+        }
+
+        Self { parts }
+    }
+}
+
+/// Whether `name` can be assigned to, as the `NAME` in `NAME=value`
+pub fn is_assignment_name(name: &str) -> bool {
+    let mut name_chars = name.chars();
+
+    name_chars
+        .next()
+        .is_some_and(|first_char| first_char.is_ascii_alphabetic() || first_char == '_')
+        && name_chars.all(|name_char| name_char.is_ascii_alphanumeric() || name_char == '_')
+}
+
+impl WordPart {
+    pub fn is_quoted(&self) -> bool {
+        match self {
+            Self::Literal { quoted, .. }
+            | Self::Parameter { quoted, .. }
+            | Self::CommandSubstitution { quoted, .. }
+            | Self::Arithmetic { quoted, .. } => *quoted,
+            Self::Tilde { .. } => false,
+        }
+    }
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal { text, .. } => write!(f, "{text}")?,
+                WordPart::Parameter { parameter, .. } => write!(f, "{parameter}")?,
+                WordPart::CommandSubstitution { command, .. } => write!(f, "$({command})")?,
+                WordPart::Arithmetic { expression, .. } => write!(f, "$(({expression}))")?,
+                WordPart::Tilde { user } => write!(f, "~{user}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `|`
+    Pipe,
+    /// `|&`, pipes standard error along with standard out
+    PipeBoth,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `;`
+    Semicolon,
+    /// `&` on its own, runs the pipeline before it in the background
+    Background,
+    /// `<`, `>`, `>>`, `2>` and friends, redirecting the given file descriptor
+    Redirect { fd: u32, kind: RedirectKind },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `>`, truncate the file before writing
+    Write,
+    /// `>|`, like `>` but overwrites the file even when `noclobber` is set
+    Clobber,
+    /// `>>`, append to the end of the file
+    Append,
+    /// `>&`, make the fd a copy of the fd given as the target, or close it with `-`
+    Duplicate,
+    /// `&>`, send both standard out and standard error to the file
+    WriteBoth,
+    /// `&>>`, append both standard out and standard error to the file
+    AppendBoth,
+    /// `<`, read from the file
+    Read,
+    /// `<>`, open the file for both reading and writing, creating it if needed
+    ReadWrite,
+    /// `<&`, like `>&` but for an fd that is read from
+    DuplicateInput,
+    /// `<<` or `<<-`, read the lines that follow up to a delimiter. The target of the
+    /// redirect is the text of those lines rather than a filename.
+    HereDocument { strip_tabs: bool },
+    /// `<<<`, read a single word followed by a newline
+    HereString,
+}
+
+impl RedirectKind {
+    /// Whether the redirect reads into the command, which makes `0` its default fd
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Self::Read
+                | Self::ReadWrite
+                | Self::DuplicateInput
+                | Self::HereDocument { .. }
+                | Self::HereString
+        )
+    }
+}
+
+impl Operator {
+    /// Reads the rest of an operator that starts with `first_char`. `fd` is the file
+    /// descriptor number written directly in front of a redirect, as in `2>`.
+    fn lex(first_char: char, fd: Option<u32>, chars: &mut Peekable<Chars>) -> Self {
+        match first_char {
+            '|' if chars.next_if_eq(&'|').is_some() => Self::Or,
+            '|' if chars.next_if_eq(&'&').is_some() => Self::PipeBoth,
+            '|' => Self::Pipe,
+            '&' if chars.next_if_eq(&'&').is_some() => Self::And,
+            '&' if chars.peek() != Some(&'>') => Self::Background,
+            '&' => {
+                chars.next_if_eq(&'>');
+
+                let kind = if chars.next_if_eq(&'>').is_some() {
+                    RedirectKind::AppendBoth
+                } else {
+                    RedirectKind::WriteBoth
+                };
+
+                Self::Redirect { fd: 1, kind }
+            }
+            ';' => Self::Semicolon,
+            '<' if chars.next_if_eq(&'<').is_some() => {
+                let kind = if chars.next_if_eq(&'<').is_some() {
+                    RedirectKind::HereString
+                } else {
+                    RedirectKind::HereDocument {
+                        strip_tabs: chars.next_if_eq(&'-').is_some(),
+                    }
+                };
+
+                Self::Redirect {
+                    fd: fd.unwrap_or(0),
+                    kind,
+                }
+            }
+            '<' if chars.next_if_eq(&'>').is_some() => Self::Redirect {
+                fd: fd.unwrap_or(0),
+                kind: RedirectKind::ReadWrite,
+            },
+            '<' if chars.next_if_eq(&'&').is_some() => Self::Redirect {
+                fd: fd.unwrap_or(0),
+                kind: RedirectKind::DuplicateInput,
+            },
+            '<' => Self::Redirect {
+                fd: fd.unwrap_or(0),
+                kind: RedirectKind::Read,
+            },
+            '>' if chars.next_if_eq(&'&').is_some() => Self::Redirect {
+                fd: fd.unwrap_or(1),
+                kind: RedirectKind::Duplicate,
+            },
+            '>' if chars.next_if_eq(&'|').is_some() => Self::Redirect {
+                fd: fd.unwrap_or(1),
+                kind: RedirectKind::Clobber,
+            },
+            '>' if chars.next_if_eq(&'>').is_some() => Self::Redirect {
+                fd: fd.unwrap_or(1),
+                kind: RedirectKind::Append,
+            },
+            '>' => Self::Redirect {
+                fd: fd.unwrap_or(1),
+                kind: RedirectKind::Write,
+            },
+            _ => unreachable!("{first_char} does not start an operator"),
+        }
+    }
+
+    /// Whether `character` begins an operator
+    fn starts_with(character: char) -> bool {
+        matches!(character, '|' | '&' | ';' | '<' | '>')
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pipe => write!(f, "|"),
+            Self::PipeBoth => write!(f, "|&"),
+            Self::And => write!(f, "&&"),
+            Self::Or => write!(f, "||"),
+            Self::Semicolon => write!(f, ";"),
+            Self::Background => write!(f, "&"),
+            Self::Redirect { fd, kind } => {
+                let default_fd = if kind.is_input() { 0 } else { 1 };
+                let fd = if *fd == default_fd {
+                    String::new()
+                } else {
+                    fd.to_string()
+                };
+                match kind {
+                    RedirectKind::Read => write!(f, "{fd}<"),
+                    RedirectKind::ReadWrite => write!(f, "{fd}<>"),
+                    RedirectKind::DuplicateInput => write!(f, "{fd}<&"),
+                    RedirectKind::HereDocument { strip_tabs: false } => write!(f, "{fd}<<"),
+                    RedirectKind::HereDocument { strip_tabs: true } => write!(f, "{fd}<<-"),
+                    RedirectKind::HereString => write!(f, "{fd}<<<"),
+                    RedirectKind::Write => write!(f, "{fd}>"),
+                    RedirectKind::Clobber => write!(f, "{fd}>|"),
+                    RedirectKind::Append => write!(f, "{fd}>>"),
+                    RedirectKind::Duplicate => write!(f, "{fd}>&"),
+                    RedirectKind::WriteBoth => write!(f, "&>"),
+                    RedirectKind::AppendBoth => write!(f, "&>>"),
+                }
+            }
+        }
+    }
+}
+
+impl Token {
+    pub fn is_operator(&self, operator: Operator) -> bool {
+        matches!(self, Self::Operator(token_operator) if *token_operator == operator)
+    }
+}
 
 enum ProcessInputState {
     Escaping,
     InsideSingleQuotes,
+    /// `$'...'`, where backslash escapes like `\n` are decoded
+    InsideAnsiCQuotes,
     InsideDoubleQuotes,
     InsideDoubleQuotesEscaping,
     Normal,
 }
 
 impl ProcessInputState {
-    pub fn inside_quotes(&self) -> bool {
-        matches!(self, Self::InsideSingleQuotes) || matches!(self, Self::InsideDoubleQuotes)
-    }
-
-    pub fn to_normal(&mut self) {
+    pub fn set_normal(&mut self) {
         *self = Self::Normal;
     }
 
-    pub fn to_double_quoting(&mut self) {
+    pub fn set_double_quoting(&mut self) {
         *self = Self::InsideDoubleQuotes;
     }
 
-    pub fn to_double_quote_escaping(&mut self) {
+    pub fn set_double_quote_escaping(&mut self) {
         *self = Self::InsideDoubleQuotesEscaping;
     }
 
-    pub fn to_single_quoting(&mut self) {
+    pub fn set_single_quoting(&mut self) {
         *self = Self::InsideSingleQuotes;
     }
 
-    pub fn to_escaping(&mut self) {
+    pub fn set_ansi_c_quoting(&mut self) {
+        *self = Self::InsideAnsiCQuotes;
+    }
+
+    pub fn set_escaping(&mut self) {
         *self = Self::Escaping;
     }
 }
 
-pub fn parse_input(input: String) -> VecDeque<String> {
+/// What the text being tokenized is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenizeMode {
+    /// A full line of input, split into words and operators
+    Line,
+    /// A single word, where spaces and operator characters are kept as they are
+    Word,
+    /// The expression of `$((...))` or `((...))`, a single word without tilde prefixes or
+    /// extglob groups
+    Arithmetic,
+    /// The body of a here-document with an unquoted delimiter, which expands like a double
+    /// quoted string where `"` is an ordinary character
+    HereDocument,
+}
+
+/// Splits a line of input into words and operators. Extglob groups like `@(a|b)` are only
+/// taken as part of a word when `extglob` is set, otherwise their `(` is a syntax error.
+pub fn parse_input(input: String, extglob: bool) -> Result<VecDeque<Token>, CustomError> {
+    let tokens = tokenize(input.trim_start(), TokenizeMode::Line, extglob)?;
+    let tokens = expand_braces(tokens)?;
+
+    Ok(tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => Token::Word(word.with_tilde_prefixes()),
+            token => token,
+        })
+        .collect())
+}
+
+/// Parses text that is always a single word, like the `word` in `${NAME:-word}`. Spaces and
+/// operator characters in it are kept as they are.
+pub fn parse_word(input: &str) -> Result<Word, CustomError> {
+    single_word(tokenize(input, TokenizeMode::Word, false)?).map(Word::with_tilde_prefixes)
+}
+
+/// Parses the body of a here-document whose delimiter wasn't quoted
+fn parse_here_document(input: &str) -> Result<Word, CustomError> {
+    single_word(tokenize(input, TokenizeMode::HereDocument, false)?)
+}
+
+/// Parses an arithmetic expression, which expands like a word but leaves `~` alone
+fn parse_arithmetic(input: &str) -> Result<Word, CustomError> {
+    single_word(tokenize(input, TokenizeMode::Arithmetic, false)?)
+}
+
+fn single_word(mut tokens: VecDeque<Token>) -> Result<Word, CustomError> {
+    match tokens.pop_front() {
+        Some(Token::Word(word)) => Ok(word),
+        _ => Ok(Word { parts: vec![] }),
+    }
+}
+
+/// Only a line of input is split into words, so `extglob` makes no difference to the
+/// other modes
+fn tokenize(
+    input: &str,
+    mode: TokenizeMode,
+    extglob: bool,
+) -> Result<VecDeque<Token>, CustomError> {
     let mut result = VecDeque::new();
-    let mut current_argument = String::new();
-    let mut state = ProcessInputState::Normal;
+    let mut current_word = WordBuilder::default();
+    let mut state = if mode == TokenizeMode::HereDocument {
+        ProcessInputState::InsideDoubleQuotes
+    } else {
+        ProcessInputState::Normal
+    };
+    // Here-documents whose operators come at or after this token still need their bodies
+    let mut here_documents_start = 0;
+    // How many extglob groups like `+(a|b)` we are inside, where `|` and spaces are part of
+    // the word
+    let mut group_depth = 0;
+    let splitting = |state: &ProcessInputState, group_depth: usize| {
+        mode == TokenizeMode::Line && matches!(state, ProcessInputState::Normal) && group_depth == 0
+    };
+
+    let mut chars = input.chars().peekable();
 
-    for argument_char in input.trim().chars() {
+    while let Some(argument_char) = chars.next() {
         if matches!(state, ProcessInputState::Escaping) {
-            current_argument.push(argument_char);
-            state.to_normal();
+            // a backslash at the end of a line joins it with the next one
+            if argument_char != '\n' {
+                current_word.push_char(argument_char, true);
+            }
+
+            state.set_normal();
             continue;
         }
 
         match argument_char {
             '\'' => match state {
-                ProcessInputState::InsideSingleQuotes => state.to_normal(),
-                ProcessInputState::InsideDoubleQuotes => current_argument.push(argument_char),
+                ProcessInputState::InsideSingleQuotes | ProcessInputState::InsideAnsiCQuotes => {
+                    state.set_normal()
+                }
+                ProcessInputState::InsideDoubleQuotes => {
+                    current_word.push_char(argument_char, true)
+                }
                 ProcessInputState::InsideDoubleQuotesEscaping => {
-                    current_argument.push('\\');
-                    current_argument.push(argument_char);
-                    state.to_double_quoting();
+                    current_word.push_char('\\', true);
+                    current_word.push_char(argument_char, true);
+                    state.set_double_quoting();
+                }
+                ProcessInputState::Normal => {
+                    current_word.start_quoting();
+                    state.set_single_quoting();
                 }
-                ProcessInputState::Normal => state.to_single_quoting(),
                 _ => (),
             },
+            '"' if mode == TokenizeMode::HereDocument => {
+                if matches!(state, ProcessInputState::InsideDoubleQuotesEscaping) {
+                    current_word.push_char('\\', true);
+                    state.set_double_quoting();
+                }
+
+                current_word.push_char(argument_char, true);
+            }
             '"' => match state {
-                ProcessInputState::InsideSingleQuotes => current_argument.push(argument_char),
-                ProcessInputState::InsideDoubleQuotes => state.to_normal(),
+                ProcessInputState::InsideSingleQuotes | ProcessInputState::InsideAnsiCQuotes => {
+                    current_word.push_char(argument_char, true)
+                }
+                ProcessInputState::InsideDoubleQuotes => state.set_normal(),
                 ProcessInputState::InsideDoubleQuotesEscaping => {
-                    current_argument.push(argument_char);
-                    state.to_double_quoting();
+                    current_word.push_char(argument_char, true);
+                    state.set_double_quoting();
+                }
+                ProcessInputState::Normal => {
+                    current_word.start_quoting();
+                    state.set_double_quoting();
                 }
-                ProcessInputState::Normal => state.to_double_quoting(),
                 _ => (),
             },
-            '~' => {
-                if matches!(state, ProcessInputState::InsideSingleQuotes) {
-                    current_argument.push(argument_char);
+            '$' if matches!(
+                state,
+                ProcessInputState::Normal | ProcessInputState::InsideDoubleQuotes
+            ) =>
+            {
+                let quoted = matches!(state, ProcessInputState::InsideDoubleQuotes);
+
+                if !quoted
+                    && let Some(quote) = chars.next_if(|&next_char| matches!(next_char, '\'' | '"'))
+                {
+                    // `$"..."` would be translated for the current locale, which we don't
+                    // support, so it is an ordinary double quoted string
+                    current_word.start_quoting();
+
+                    if quote == '\'' {
+                        state.set_ansi_c_quoting();
+                    } else {
+                        state.set_double_quoting();
+                    }
+                    continue;
+                }
+
+                if starts_with_double_paren(&chars) {
+                    let expression = read_arithmetic(&mut chars)?;
+                    current_word.push_part(WordPart::Arithmetic { expression, quoted });
+                    continue;
+                }
+
+                if chars.next_if_eq(&'(').is_some() {
+                    let command = read_until_closing_paren(&mut chars)?;
+                    current_word.push_part(WordPart::CommandSubstitution { command, quoted });
+                    continue;
+                }
+
+                match lex_parameter(&mut chars)? {
+                    Some(parameter) => {
+                        current_word.push_part(WordPart::Parameter { parameter, quoted })
+                    }
+                    None => current_word.push_char(argument_char, quoted),
+                }
+            }
+            '`' if matches!(
+                state,
+                ProcessInputState::Normal | ProcessInputState::InsideDoubleQuotes
+            ) =>
+            {
+                let quoted = matches!(state, ProcessInputState::InsideDoubleQuotes);
+                let command = read_backquoted(&mut chars)?;
+                current_word.push_part(WordPart::CommandSubstitution { command, quoted });
+            }
+            '(' if splitting(&state, group_depth)
+                && current_word.is_empty()
+                && chars.peek() == Some(&'(') =>
+            {
+                chars.next();
+                let expression = read_until_double_paren(&mut chars)?;
+                result.push_back(Token::Arithmetic(parse_arithmetic(&expression)?));
+            }
+            '?' | '*' | '+' | '@' | '!'
+                if extglob
+                    && mode != TokenizeMode::Arithmetic
+                    && matches!(state, ProcessInputState::Normal)
+                    && chars.peek() == Some(&'(') =>
+            {
+                chars.next();
+                current_word.push_char(argument_char, false);
+                current_word.push_char('(', false);
+                group_depth += 1;
+            }
+            '(' | ')' if group_depth > 0 && matches!(state, ProcessInputState::Normal) => {
+                if argument_char == '(' {
+                    group_depth += 1;
                 } else {
-                    let home_directory = std::env::home_dir().unwrap_or_default();
-                    current_argument.push_str(home_directory.to_str().unwrap_or_default());
+                    group_depth -= 1;
                 }
+
+                current_word.push_char(argument_char, false);
             }
-            ' ' => {
-                if state.inside_quotes() {
-                    current_argument.push(argument_char);
-                } else if !current_argument.is_empty() {
-                    result.push_back(current_argument.clone());
-                    current_argument.clear();
+            '(' | ')' if splitting(&state, group_depth) => {
+                return Err(CustomError::UnexpectedToken(argument_char.to_string()));
+            }
+            '\n' if matches!(state, ProcessInputState::InsideDoubleQuotesEscaping) => {
+                state.set_double_quoting();
+            }
+            '\n' if splitting(&state, group_depth) => {
+                current_word.finish(&mut result);
+                read_here_documents(&mut result, here_documents_start, &mut chars)?;
+                here_documents_start = result.len();
+
+                // a newline ends the command like `;` does, unless the command so far is
+                // waiting for more
+                if result.back().is_some_and(|token| {
+                    !matches!(
+                        token,
+                        Token::Operator(
+                            Operator::Pipe
+                                | Operator::PipeBoth
+                                | Operator::And
+                                | Operator::Or
+                                | Operator::Semicolon
+                                | Operator::Background
+                        )
+                    )
+                }) {
+                    result.push_back(Token::Operator(Operator::Semicolon));
+                }
+            }
+            '#' if splitting(&state, group_depth) && current_word.is_empty() => {
+                // a comment runs to the end of the line, leaving the newline to end the command
+                while chars
+                    .next_if(|comment_char| *comment_char != '\n')
+                    .is_some()
+                {}
+            }
+            ' ' | '\t' if splitting(&state, group_depth) => {
+                current_word.finish(&mut result);
+            }
+            _ if splitting(&state, group_depth) && Operator::starts_with(argument_char) => {
+                let redirect_fd = if matches!(argument_char, '<' | '>') {
+                    current_word.fd_number()
+                } else {
+                    None
+                };
+
+                if redirect_fd.is_some() {
+                    current_word = WordBuilder::default();
+                } else {
+                    current_word.finish(&mut result);
                 }
+
+                let operator = Operator::lex(argument_char, redirect_fd, &mut chars);
+                result.push_back(Token::Operator(operator));
             }
             '\\' => match state {
-                ProcessInputState::InsideSingleQuotes => current_argument.push(argument_char),
-                ProcessInputState::InsideDoubleQuotes => state.to_double_quote_escaping(),
+                ProcessInputState::InsideSingleQuotes => {
+                    current_word.push_char(argument_char, true)
+                }
+                ProcessInputState::InsideAnsiCQuotes => {
+                    for escaped_char in read_ansi_c_escape(&mut chars).chars() {
+                        current_word.push_char(escaped_char, true);
+                    }
+                }
+                ProcessInputState::InsideDoubleQuotes => state.set_double_quote_escaping(),
                 ProcessInputState::InsideDoubleQuotesEscaping => {
-                    current_argument.push(argument_char);
-                    state.to_double_quoting();
+                    current_word.push_char(argument_char, true);
+                    state.set_double_quoting();
                 }
-                ProcessInputState::Normal => state.to_escaping(),
+                ProcessInputState::Normal => state.set_escaping(),
                 _ => (),
             },
             _ => {
                 if matches!(state, ProcessInputState::InsideDoubleQuotesEscaping) {
-                    state.to_double_quoting();
-                    current_argument.push('\\');
+                    state.set_double_quoting();
+
+                    if !matches!(argument_char, '$' | '`') {
+                        current_word.push_char('\\', true);
+                    }
                 }
-                current_argument.push(argument_char);
+
+                let quoted = !matches!(state, ProcessInputState::Normal);
+                current_word.push_char(argument_char, quoted);
             }
         }
     }
 
-    if !current_argument.is_empty() {
-        result.push_back(current_argument);
+    match state {
+        ProcessInputState::Escaping => return Err(CustomError::UnexpectedEndOfInput),
+        ProcessInputState::InsideSingleQuotes | ProcessInputState::InsideAnsiCQuotes => {
+            return Err(CustomError::UnmatchedQuote('\''));
+        }
+        ProcessInputState::InsideDoubleQuotes | ProcessInputState::InsideDoubleQuotesEscaping
+            if mode != TokenizeMode::HereDocument =>
+        {
+            return Err(CustomError::UnmatchedQuote('"'));
+        }
+        _ => (),
+    }
+
+    // an extglob group like `@(a b` that is never closed leaves the input unfinished
+    if mode == TokenizeMode::Line && group_depth > 0 {
+        return Err(CustomError::UnexpectedEndOfInput);
+    }
+
+    current_word.finish(&mut result);
+
+    if let Some(delimiter) = here_document_delimiters(&result, here_documents_start).next() {
+        return Err(CustomError::UnterminatedHereDocument(delimiter.to_string()));
     }
 
-    result
+    Ok(result)
+}
+
+/// The here-documents at or after `start` in `tokens`, as the index of the delimiter word
+/// that follows each operator
+fn here_document_delimiters(tokens: &VecDeque<Token>, start: usize) -> impl Iterator<Item = usize> {
+    (start..tokens.len()).filter_map(|index| {
+        let is_here_document = matches!(
+            tokens[index],
+            Token::Operator(Operator::Redirect {
+                kind: RedirectKind::HereDocument { .. },
+                ..
+            })
+        );
+        let has_delimiter = matches!(tokens.get(index + 1), Some(Token::Word(_)));
+
+        (is_here_document && has_delimiter).then_some(index + 1)
+    })
 }
 
-pub fn input_for_one_command(user_input: &mut VecDeque<String>) -> VecDeque<String> {
+/// Reads the bodies of the here-documents started on the line that just ended. They follow
+/// that line one after another, and each body takes the place of its delimiter word.
+fn read_here_documents(
+    tokens: &mut VecDeque<Token>,
+    start: usize,
+    chars: &mut Peekable<Chars>,
+) -> Result<(), CustomError> {
+    let delimiter_indexes = here_document_delimiters(tokens, start).collect::<Vec<usize>>();
+
+    for delimiter_index in delimiter_indexes {
+        let (
+            Token::Operator(Operator::Redirect {
+                kind: RedirectKind::HereDocument { strip_tabs },
+                ..
+            }),
+            Token::Word(delimiter),
+        ) = (&tokens[delimiter_index - 1], &tokens[delimiter_index])
+        else {
+            unreachable!("found by here_document_delimiters");
+        };
+        let body = read_here_document_body(chars, &delimiter.to_string(), *strip_tabs)?;
+
+        // quoting any part of the delimiter stops the body from being expanded
+        let body = if delimiter.is_quoted() {
+            Word {
+                parts: vec![WordPart::Literal {
+                    text: body,
+                    quoted: true,
+                }],
+            }
+        } else {
+            parse_here_document(&body)?
+        };
+
+        tokens[delimiter_index] = Token::Word(body);
+    }
+
+    Ok(())
+}
+
+/// Reads lines up to the one that is just `delimiter`, removing their leading tabs first for
+/// `<<-`
+fn read_here_document_body(
+    chars: &mut Peekable<Chars>,
+    delimiter: &str,
+    strip_tabs: bool,
+) -> Result<String, CustomError> {
+    let mut body = String::new();
+
+    while chars.peek().is_some() {
+        let line = chars
+            .by_ref()
+            .take_while(|line_char| *line_char != '\n')
+            .collect::<String>();
+        let line = if strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            &line
+        };
+
+        if line == delimiter {
+            return Ok(body);
+        }
+
+        body.push_str(line);
+        body.push('\n');
+    }
+
+    Err(CustomError::UnterminatedHereDocument(delimiter.to_owned()))
+}
+
+/// Reads a parameter name following a `$`, either bare like `$HOME` and `$?` or wrapped in
+/// braces like `${HOME}`. A `$` that isn't followed by a name is just a dollar sign.
+fn lex_parameter(chars: &mut Peekable<Chars>) -> Result<Option<Parameter>, CustomError> {
+    let Some(&next_char) = chars.peek() else {
+        return Ok(None);
+    };
+
+    if next_char == '{' {
+        chars.next();
+        let braced = read_until_closing_brace(chars)?;
+
+        return Parameter::parse(&braced).map(Some);
+    }
+
+    if Parameter::is_special(next_char) {
+        chars.next();
+
+        return Ok(Some(Parameter::new(next_char.to_string())));
+    }
+
+    if next_char.is_ascii_alphabetic() || next_char == '_' {
+        let mut name = String::new();
+
+        while let Some(name_char) =
+            chars.next_if(|name_char| name_char.is_ascii_alphanumeric() || *name_char == '_')
+        {
+            name.push(name_char);
+        }
+
+        return Ok(Some(Parameter::new(name)));
+    }
+
+    Ok(None)
+}
+
+/// Reads everything up to the `}` that closes an already consumed `{`, skipping over any
+/// nested braces and quoted text along the way.
+fn read_until_closing_brace(chars: &mut Peekable<Chars>) -> Result<String, CustomError> {
+    let mut braced = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+
+    while let Some(braced_char) = chars.next() {
+        match (braced_char, quote) {
+            ('\\', Some('\'')) => (),
+            ('\\', _) => {
+                braced.push(braced_char);
+                if let Some(escaped_char) = chars.next() {
+                    braced.push(escaped_char);
+                }
+                continue;
+            }
+            (_, Some(quote_char)) if braced_char == quote_char => quote = None,
+            (_, Some(_)) => (),
+            ('\'' | '"', None) => quote = Some(braced_char),
+            ('{', None) => depth += 1,
+            ('}', None) if depth == 0 => return Ok(braced),
+            ('}', None) => depth -= 1,
+            _ => (),
+        }
+
+        braced.push(braced_char);
+    }
+
+    Err(CustomError::UnexpectedEndOfInput)
+}
+
+/// Reads everything up to the `)` that closes an already consumed `$(`, skipping over any
+/// nested parentheses and quoted text along the way.
+fn read_until_closing_paren(chars: &mut Peekable<Chars>) -> Result<String, CustomError> {
+    let mut command = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+
+    while let Some(command_char) = chars.next() {
+        match (command_char, quote) {
+            ('\\', Some('\'')) => (),
+            ('\\', _) => {
+                command.push(command_char);
+                if let Some(escaped_char) = chars.next() {
+                    command.push(escaped_char);
+                }
+                continue;
+            }
+            (_, Some(quote_char)) if command_char == quote_char => quote = None,
+            (_, Some(_)) => (),
+            ('\'' | '"' | '`', None) => quote = Some(command_char),
+            ('(', None) => depth += 1,
+            (')', None) if depth == 0 => return Ok(command),
+            (')', None) => depth -= 1,
+            _ => (),
+        }
+
+        command.push(command_char);
+    }
+
+    Err(CustomError::UnexpectedEndOfInput)
+}
+
+fn starts_with_double_paren(chars: &Peekable<Chars>) -> bool {
+    let mut lookahead = chars.clone();
+
+    lookahead.next() == Some('(') && lookahead.next() == Some('(')
+}
+
+/// Reads the expression of `$((expression))` once the `$` has been consumed
+fn read_arithmetic(chars: &mut Peekable<Chars>) -> Result<Word, CustomError> {
+    chars.next();
+    chars.next();
+
+    parse_arithmetic(&read_until_double_paren(chars)?)
+}
+
+/// Reads up to the `))` that closes an already consumed `((`
+fn read_until_double_paren(chars: &mut Peekable<Chars>) -> Result<String, CustomError> {
+    let expression = read_until_closing_paren(chars)?;
+
+    match chars.next() {
+        Some(')') => Ok(expression),
+        Some(unexpected_char) => Err(CustomError::UnexpectedToken(unexpected_char.to_string())),
+        None => Err(CustomError::UnexpectedEndOfInput),
+    }
+}
+
+/// Decodes the escape sequence after a backslash inside `$'...'`. Sequences that don't mean
+/// anything keep their backslash, like bash does.
+fn read_ansi_c_escape(chars: &mut Peekable<Chars>) -> String {
+    let Some(escaped_char) = chars.next() else {
+        return "\\".to_owned();
+    };
+
+    let decoded = match escaped_char {
+        'a' => '\u{07}',
+        'b' => '\u{08}',
+        'e' | 'E' => '\u{1b}',
+        'f' => '\u{0c}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\u{0b}',
+        '\\' | '\'' | '"' | '?' => escaped_char,
+        '0'..='7' => {
+            let mut digits = escaped_char.to_string();
+            digits.push_str(&read_digits(chars, 8, 2));
+
+            // like bash, only the low eight bits of an octal escape are kept
+            let value = u32::from_str_radix(&digits, 8).unwrap_or_default() & 0xff;
+            char::from_u32(value).unwrap_or_default()
+        }
+        'x' | 'u' | 'U' => {
+            let max_digits = match escaped_char {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            let digits = read_digits(chars, 16, max_digits);
+
+            if digits.is_empty() {
+                return format!("\\{escaped_char}");
+            }
+
+            let value = u32::from_str_radix(&digits, 16).unwrap_or_default();
+            char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+        'c' => match chars.next() {
+            Some('?') => '\u{7f}',
+            Some(control_char) => {
+                char::from_u32(u32::from(control_char.to_ascii_uppercase()) & 0x1f)
+                    .unwrap_or_default()
+            }
+            None => return "\\c".to_owned(),
+        },
+        _ => return format!("\\{escaped_char}"),
+    };
+
+    decoded.to_string()
+}
+
+/// Reads up to `max_digits` digits in `radix`
+fn read_digits(chars: &mut Peekable<Chars>, radix: u32, max_digits: usize) -> String {
+    let mut digits = String::new();
+
+    while digits.len() < max_digits
+        && let Some(digit) = chars.next_if(|digit| digit.is_digit(radix))
+    {
+        digits.push(digit);
+    }
+
+    digits
+}
+
+/// Reads the command of an old style `` `command` `` substitution, up to the closing
+/// backquote. Inside, a backslash only escapes `$`, `` ` `` and another backslash.
+fn read_backquoted(chars: &mut Peekable<Chars>) -> Result<String, CustomError> {
+    let mut command = String::new();
+
+    while let Some(command_char) = chars.next() {
+        match command_char {
+            '`' => return Ok(command),
+            '\\' => match chars.next_if(|escaped_char| matches!(escaped_char, '$' | '`' | '\\')) {
+                Some(escaped_char) => command.push(escaped_char),
+                None => command.push(command_char),
+            },
+            _ => command.push(command_char),
+        }
+    }
+
+    Err(CustomError::UnexpectedEndOfInput)
+}
+
+/// Runs brace expansion on every word of a line. It happens before all of the other
+/// expansions, so `{a,b}` turns into separate words as if they had been typed out.
+fn expand_braces(tokens: VecDeque<Token>) -> Result<VecDeque<Token>, CustomError> {
+    let mut expanded = VecDeque::new();
+    let mut after_redirect = false;
+
+    for token in tokens {
+        match token {
+            Token::Word(word) => {
+                let words = brace_expand(&word);
+
+                if after_redirect && words.len() != 1 {
+                    return Err(CustomError::AmbiguousRedirect(word.to_string()));
+                }
+
+                expanded.extend(words.into_iter().map(Token::Word));
+                after_redirect = false;
+            }
+            token => {
+                after_redirect = matches!(token, Token::Operator(Operator::Redirect { .. }));
+                expanded.push_back(token);
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// A piece of a word during brace expansion. Only unquoted characters can be braces or
+/// commas, everything else is carried along untouched.
+#[derive(Debug, Clone)]
+enum BraceUnit {
+    Unquoted(char),
+    Part(WordPart),
+}
+
+impl BraceUnit {
+    fn is(&self, character: char) -> bool {
+        matches!(self, Self::Unquoted(unit_char) if *unit_char == character)
+    }
+}
+
+fn brace_expand(word: &Word) -> Vec<Word> {
+    let mut units = vec![];
+
+    for part in &word.parts {
+        match part {
+            WordPart::Literal {
+                text,
+                quoted: false,
+            } => units.extend(text.chars().map(BraceUnit::Unquoted)),
+            _ => units.push(BraceUnit::Part(part.clone())),
+        }
+    }
+
+    if !units.iter().any(|unit| unit.is('{')) {
+        return vec![word.clone()];
+    }
+
+    expand_brace_units(units)
+        .into_iter()
+        .map(|units| {
+            let mut word_builder = WordBuilder::default();
+
+            for unit in units {
+                match unit {
+                    BraceUnit::Unquoted(unit_char) => word_builder.push_char(unit_char, false),
+                    BraceUnit::Part(part) => word_builder.push_part(part),
+                }
+            }
+
+            Word {
+                parts: word_builder.parts,
+            }
+        })
+        .collect()
+}
+
+/// Expands the first brace expression in `units` that is either a list like `{a,b}` or a
+/// sequence like `{1..5}`, then the rest of each result. Braces that are neither are left
+/// as they are.
+fn expand_brace_units(units: Vec<BraceUnit>) -> Vec<Vec<BraceUnit>> {
+    for open in 0..units.len() {
+        if !units[open].is('{') {
+            continue;
+        }
+
+        let Some((close, commas)) = find_closing_brace(&units, open) else {
+            continue;
+        };
+
+        let items = if !commas.is_empty() {
+            let mut boundaries = vec![open];
+            boundaries.extend(commas);
+            boundaries.push(close);
+
+            boundaries
+                .windows(2)
+                .map(|window| units[window[0] + 1..window[1]].to_vec())
+                .collect::<Vec<Vec<BraceUnit>>>()
+        } else if let Some(sequence) = brace_sequence(&units[open + 1..close]) {
+            sequence
+                .into_iter()
+                .map(|item| item.chars().map(BraceUnit::Unquoted).collect())
+                .collect()
+        } else {
+            continue;
+        };
+
+        return items
+            .into_iter()
+            .flat_map(|item| {
+                let mut expanded = units[..open].to_vec();
+                expanded.extend(item);
+                expanded.extend_from_slice(&units[close + 1..]);
+
+                expand_brace_units(expanded)
+            })
+            .collect();
+    }
+
+    vec![units]
+}
+
+/// Finds the `}` matching the `{` at `open`, along with the commas directly inside it
+fn find_closing_brace(units: &[BraceUnit], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+
+    for (index, unit) in units.iter().enumerate().skip(open) {
+        if unit.is('{') {
+            depth += 1;
+        } else if unit.is('}') {
+            depth -= 1;
+
+            if depth == 0 {
+                return Some((index, commas));
+            }
+        } else if unit.is(',') && depth == 1 {
+            commas.push(index);
+        }
+    }
+
+    None
+}
+
+/// The items of a sequence expression like `1..10`, `01..10..2` or `a..e`, or `None` when
+/// the text isn't one
+fn brace_sequence(units: &[BraceUnit]) -> Option<Vec<String>> {
+    let text = units
+        .iter()
+        .map(|unit| match unit {
+            BraceUnit::Unquoted(unit_char) => Some(*unit_char),
+            BraceUnit::Part(_) => None,
+        })
+        .collect::<Option<String>>()?;
+    let (start, end, step) = match text.split("..").collect::<Vec<&str>>().as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, step.parse::<i64>().ok()?.unsigned_abs()),
+        _ => return None,
+    };
+    let step = i128::from(step.max(1));
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // a leading zero on either end pads every number to the same width
+        let is_padded = |bound: &str| {
+            let digits = bound.strip_prefix('-').unwrap_or(bound);
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if is_padded(start) || is_padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        return Some(
+            stepped_range(first.into(), last.into(), step)
+                .map(|number| format!("{number:0width$}"))
+                .collect(),
+        );
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        // like bash, only letters make a range of characters, so `{1..a}` stays as it is
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                stepped_range(u32::from(first).into(), u32::from(last).into(), step)
+                    .filter_map(|code| char::from_u32(code.try_into().ok()?))
+                    .map(String::from)
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Counts from `first` to `last` by `step`, downwards when `last` is the smaller one
+fn stepped_range(first: i128, last: i128, step: i128) -> impl Iterator<Item = i128> {
+    let step = if first <= last { step } else { -step };
+
+    std::iter::successors(Some(first), move |value| Some(value + step)).take_while(move |value| {
+        if step > 0 {
+            *value <= last
+        } else {
+            *value >= last
+        }
+    })
+}
+
+/// Collects the parts of the word currently being parsed
+#[derive(Default)]
+struct WordBuilder {
+    parts: Vec<WordPart>,
+}
+
+impl WordBuilder {
+    fn push_char(&mut self, character: char, quoted: bool) {
+        if let Some(WordPart::Literal {
+            text,
+            quoted: last_quoted,
+        }) = self.parts.last_mut()
+            && *last_quoted == quoted
+        {
+            text.push(character);
+        } else {
+            self.parts.push(WordPart::Literal {
+                text: character.to_string(),
+                quoted,
+            });
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    fn push_part(&mut self, part: WordPart) {
+        self.parts.push(part);
+    }
+
+    /// Records an opening quote, so that `""` still produces an (empty) word
+    fn start_quoting(&mut self) {
+        self.push_part(WordPart::Literal {
+            text: String::new(),
+            quoted: true,
+        });
+    }
+
+    /// The word as a file descriptor number, if it is made up of unquoted digits only
+    fn fd_number(&self) -> Option<u32> {
+        match self.parts.as_slice() {
+            [
+                WordPart::Literal {
+                    text,
+                    quoted: false,
+                },
+            ] if text.chars().all(|digit| digit.is_ascii_digit()) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn finish(&mut self, result: &mut VecDeque<Token>) {
+        if !self.parts.is_empty() {
+            let parts = std::mem::take(&mut self.parts);
+            result.push_back(Token::Word(Word { parts }));
+        }
+    }
+}
+
+/// Takes the tokens of the next pipeline off the front of the input, along with the list
+/// operator (`;`, `&`, `&&` or `||`) that ended it, if there was one.
+pub fn input_for_one_pipeline(
+    user_input: &mut VecDeque<Token>,
+) -> (VecDeque<Token>, Option<Operator>) {
+    let mut pipeline_input = VecDeque::new();
+
+    while let Some(token) = user_input.pop_front() {
+        if let Token::Operator(
+            operator @ (Operator::Semicolon | Operator::Background | Operator::And | Operator::Or),
+        ) = token
+        {
+            return (pipeline_input, Some(operator));
+        }
+
+        pipeline_input.push_back(token);
+    }
+
+    (pipeline_input, None)
+}
+
+/// Takes the tokens of the next command in a pipeline off the front of the input. A `|&`
+/// after the command is the same as `2>&1 |`.
+pub fn input_for_one_command(user_input: &mut VecDeque<Token>) -> VecDeque<Token> {
     let mut single_command_input = VecDeque::new();
 
-    while let Some(input_fragment) = user_input.pop_front() {
-        if input_fragment == "|" {
+    while let Some(token) = user_input.pop_front() {
+        if token.is_operator(Operator::Pipe) {
+            break;
+        }
+
+        if token.is_operator(Operator::PipeBoth) {
+            single_command_input.push_back(Token::Operator(Operator::Redirect {
+                fd: 2,
+                kind: RedirectKind::Duplicate,
+            }));
+            single_command_input.push_back(Token::Word(Word {
+                parts: vec![WordPart::Literal {
+                    text: "1".to_owned(),
+                    quoted: false,
+                }],
+            }));
             break;
         }
 
-        single_command_input.push_back(input_fragment);
+        single_command_input.push_back(token);
     }
 
     single_command_input
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words a line splits into once braces are expanded, as they were typed
+    fn words(input: &str) -> Vec<String> {
+        let tokens = tokenize(input, TokenizeMode::Line, false).unwrap();
+
+        expand_braces(tokens)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(word) => word.to_string(),
+                token => panic!("{token:?} isn't a word"),
+            })
+            .collect()
+    }
+
+    fn sequence(text: &str) -> Option<Vec<String>> {
+        let units = text.chars().map(BraceUnit::Unquoted).collect::<Vec<_>>();
+
+        brace_sequence(&units)
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_text_in_one_word() {
+        assert_eq!(
+            words(r#"echo "a b" 'c  d' e\ f "x"'y'z"#),
+            ["echo", "a b", "c  d", "e f", "xyz"]
+        );
+    }
+
+    #[test]
+    fn tokenize_only_lexes_unquoted_operators() {
+        let tokens = tokenize(r#"echo ">" \; >out"#, TokenizeMode::Line, false).unwrap();
+
+        assert!(matches!(
+            tokens.iter().collect::<Vec<_>>().as_slice(),
+            [
+                Token::Word(_),
+                Token::Word(quoted),
+                Token::Word(escaped),
+                Token::Operator(Operator::Redirect { fd: 1, kind: RedirectKind::Write }),
+                Token::Word(_),
+            ] if quoted.is_quoted() && escaped.is_quoted()
+        ));
+    }
+
+    #[test]
+    fn tokenize_reports_unfinished_quotes() {
+        let single = tokenize("echo 'a", TokenizeMode::Line, false);
+        let double = tokenize("echo \"a", TokenizeMode::Line, false);
+
+        assert!(matches!(single, Err(CustomError::UnmatchedQuote('\''))));
+        assert!(matches!(double, Err(CustomError::UnmatchedQuote('"'))));
+    }
+
+    #[test]
+    fn expand_braces_pads_stepped_sequences() {
+        assert_eq!(words("echo {01..10..3}"), ["echo", "01", "04", "07", "10"]);
+    }
+
+    #[test]
+    fn expand_braces_leaves_mixed_sequences_and_quoted_braces() {
+        assert_eq!(words("echo {1..a} \"{a,b}\""), ["echo", "{1..a}", "{a,b}"]);
+        assert_eq!(words("echo x{a,b}y"), ["echo", "xay", "xby"]);
+    }
+
+    #[test]
+    fn brace_sequence_counts_both_ways() {
+        assert_eq!(sequence("5..1").unwrap(), ["5", "4", "3", "2", "1"]);
+        assert_eq!(sequence("a..e..2").unwrap(), ["a", "c", "e"]);
+        assert_eq!(sequence("-1..1").unwrap(), ["-1", "0", "1"]);
+        assert_eq!(sequence("1..a"), None);
+        assert_eq!(sequence("1..2..3..4"), None);
+    }
+
+    #[test]
+    fn read_ansi_c_escape_takes_at_most_three_octal_digits() {
+        let mut chars = "0101".chars().peekable();
+
+        assert_eq!(read_ansi_c_escape(&mut chars), "\u{8}");
+        assert_eq!(chars.collect::<String>(), "1");
+    }
+
+    #[test]
+    fn read_ansi_c_escape_decodes_sequences() {
+        let decode = |text: &str| read_ansi_c_escape(&mut text.chars().peekable());
+
+        assert_eq!(decode("101"), "A");
+        assert_eq!(decode("x41"), "A");
+        assert_eq!(decode("u263a"), "☺");
+        assert_eq!(decode("cA"), "\u{1}");
+        assert_eq!(decode("n"), "\n");
+        assert_eq!(decode("q"), "\\q");
+        assert_eq!(decode("x"), "\\x");
+    }
+}
@@ -1,200 +1,440 @@
 mod builtin_commands;
 mod command;
 mod errors;
+pub mod expansion;
 mod get_user_input;
 pub mod input_parser;
 pub mod utilities;
 
 use crate::{
     builtin_commands::{
-        BuiltinCommand, builtin_type::builtin_type, change_directory::change_directory, echo::echo,
-        history::History, pwd::pwd, run_external_executable::run_external,
+        BuiltinCommand,
+        arithmetic::arithmetic,
+        builtin_type::builtin_type,
+        change_directory::change_directory,
+        echo::echo,
+        exec::exec,
+        history::History,
+        jobs::{Job, Jobs, ProcessGroup, bg, fg, jobs},
+        pwd::pwd,
+        read::read,
+        run_external_executable::run_external,
+        set::set,
+        shopt::{ShellOptions, shopt},
     },
-    command::{Command, CommandIO, parse_user_input},
-    errors::ErrorExitCode,
+    command::{
+        Command, CommandIO, FileDescriptors, Output, Pipeline, exit_code, parse_user_input,
+        report_signal,
+    },
+    errors::{CustomError, ErrorExitCode, describe_io_error},
     get_user_input::UserInput,
     utilities::{find_executable_files, get_path},
 };
 use anyhow::{Context, Result};
 use std::{
+    collections::{BTreeMap, VecDeque},
     env,
-    io::{self, BufRead, BufReader, PipeReader, Write},
-    process::{Child, Stdio},
+    io::{self, PipeReader, Read, Write},
+    ops::ControlFlow,
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::process::ExitStatusExt,
+    },
+    path::PathBuf,
+    process::ExitStatus,
 };
 
 pub fn run() -> Result<()> {
-    let path = get_path().context("Getting path")?;
-    let mut history = History::new()?;
-    let mut user_input = UserInput::new("$ ");
-
-    'repl_loop: loop {
-        let user_input_line = user_input.readline(&mut history)?;
-        let mut commands = match parse_user_input(user_input_line) {
-            Ok(commands) => commands,
+    let mut shell = Shell::new()?;
+    catch_interrupts();
+    let mut user_input = UserInput::new("$ ", "> ");
+
+    loop {
+        shell.jobs.report_changes();
+        let user_input_line = user_input.readline(&mut shell.history, shell.options.extglob)?;
+
+        if !user_input_line.trim().is_empty() {
+            shell.history.add(&user_input_line);
+        }
+
+        if shell.run_line(user_input_line)?.is_break() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The signals the terminal sends for `Ctrl-C`, `Ctrl-\` and `Ctrl-Z`
+const INTERRUPTS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP];
+
+/// Keeps `Ctrl-C`, `Ctrl-\` and `Ctrl-Z` from killing or stopping the shell along with the
+/// command it is waiting for. The signals get a handler that does nothing rather than being
+/// ignored, since the programs the shell starts would stay ignoring them too.
+fn catch_interrupts() {
+    extern "C" fn do_nothing(_signal: libc::c_int) {}
+
+    for signal in INTERRUPTS {
+        unsafe { libc::signal(signal, do_nothing as *const () as libc::sighandler_t) };
+    }
+}
+
+/// Lets the interrupts end or stop a forked copy of the shell, like any other command
+fn release_interrupts() {
+    for signal in INTERRUPTS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+/// The exit code of the last command that ran, as stored in `$?`
+fn last_exit_code() -> i32 {
+    env::var("?")
+        .ok()
+        .and_then(|exit_code| exit_code.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The state that lives for as long as the shell does
+pub struct Shell {
+    path: Vec<PathBuf>,
+    history: History,
+    options: ShellOptions,
+    /// The exit code of each command in the last pipeline, for `$PIPESTATUS`
+    pipe_status: Vec<i32>,
+    /// The descriptors from 3 up that `exec` opened, which every command starts out with
+    file_descriptors: BTreeMap<u32, Output>,
+    /// The pipelines left running with `&` or stopped with `Ctrl-Z`
+    jobs: Jobs,
+    /// Set in the forked child that runs a command substitution
+    subshell: bool,
+}
+
+impl Shell {
+    fn new() -> Result<Self> {
+        let path = get_path().context("Getting path")?;
+        let options = ShellOptions::default();
+        let history = History::new(options.extglob)?;
+
+        Ok(Self {
+            path,
+            history,
+            options,
+            pipe_status: vec![],
+            file_descriptors: BTreeMap::new(),
+            jobs: Jobs::new(),
+            subshell: false,
+        })
+    }
+
+    /// Parses and runs one line of input, breaking when the shell should exit.
+    fn run_line(&mut self, user_input_line: String) -> Result<ControlFlow<()>> {
+        let pipelines = match parse_user_input(user_input_line, self.options.extglob) {
+            Ok(pipelines) => pipelines,
             Err(error) => {
                 eprintln!("{error}");
-                continue;
+                return Ok(ControlFlow::Continue(()));
             }
         };
-        // create the pipes here
-        let mut previous_commands_stdout_reader: Option<PipeReader> = None;
-        let mut previous_external_child: Option<Child> = None;
-        let mut last_command: Option<Command> = None;
-
-        while let Some(command) = commands.pop_front() {
-            let current_command = command.clone();
-
-            history.add(&command);
-
-            let (mut stderr_reader, stderr_writer) = io::pipe()?;
-            let (mut stdout_reader, stdout_writer) = io::pipe()?;
-            let command_io_stdin = if let Some(unwrapped_last_command) = last_command.as_ref() {
-                if unwrapped_last_command.builtin_command.is_builtin() {
-                    Some(Stdio::from(previous_commands_stdout_reader.take().unwrap()))
-                } else {
-                    let Some(last_child) = previous_external_child.take() else {
-                        unreachable!();
-                    };
-                    Some(Stdio::from(last_child.stdout.unwrap()))
+
+        for pipeline in pipelines {
+            if !pipeline.list_operator.should_run(last_exit_code()) {
+                continue;
+            }
+
+            if self.run_pipeline(pipeline)?.is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Runs `$(command)` in a forked copy of the shell and returns what it wrote to standard
+    /// out, without the trailing newlines.
+    pub fn command_substitution(&mut self, command: &str) -> Result<String, CustomError> {
+        let (mut output_reader, output_writer) = io::pipe()?;
+
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error().into()),
+            0 => {
+                drop(output_reader);
+                unsafe { libc::dup2(output_writer.as_raw_fd(), libc::STDOUT_FILENO) };
+                drop(output_writer);
+
+                self.enter_subshell();
+                let exit_code = match self.run_line(command.to_owned()) {
+                    Ok(_) => last_exit_code(),
+                    Err(error) => {
+                        eprintln!("{error}");
+                        1
+                    }
+                };
+
+                let _ = io::stdout().flush();
+                unsafe { libc::_exit(exit_code) }
+            }
+            child_pid => {
+                drop(output_writer);
+
+                let mut output = vec![];
+                output_reader.read_to_end(&mut output)?;
+
+                let mut status = 0;
+                unsafe { libc::waitpid(child_pid, &mut status, 0) };
+                let exit_status = ExitStatus::from_raw(status);
+                report_signal(exit_status);
+
+                let exit_code = exit_code(exit_status);
+                unsafe { env::set_var("?", exit_code.to_string()) }
+
+                let output = String::from_utf8_lossy(&output);
+                Ok(output.trim_end_matches('\n').to_owned())
+            }
+        }
+    }
+
+    /// A forked copy of the shell leaves job control to the shell it came from
+    fn enter_subshell(&mut self) {
+        self.subshell = true;
+        self.jobs.leave_terminal();
+    }
+
+    /// Runs every command in a pipeline, breaking when the shell should exit. A pipeline
+    /// that ends in `&` is left running as a job.
+    fn run_pipeline(&mut self, pipeline: Pipeline) -> Result<ControlFlow<()>> {
+        let mut commands = VecDeque::new();
+        let mut job = Job::new(pipeline.to_string());
+        let is_foreground = !pipeline.background;
+
+        for simple_command in &pipeline.commands {
+            match Command::new(simple_command, self) {
+                // kept with its text for `jobs -l`, which lists each process on its own
+                Ok(Some(command)) => commands.push_back((simple_command.to_string(), command)),
+                Ok(None) => (),
+                Err(error) => {
+                    eprintln!("{error}");
+                    self.pipe_status = vec![1];
+                    unsafe { env::set_var("?", "1") }
+                    return Ok(ControlFlow::Continue(()));
                 }
-            } else {
+            }
+        }
+
+        let is_single_command = commands.len() == 1;
+        let mut previous_commands_stdout_reader: Option<PipeReader> = None;
+
+        while let Some((command_text, command)) = commands.pop_front() {
+            let is_last_command = commands.is_empty();
+            // a redirect takes the place of whatever the previous command in the pipeline wrote
+            let stdin = command
+                .file_descriptors
+                .standard_in
+                .open_reader(previous_commands_stdout_reader.take())?;
+
+            // the pipe to the next command only gets a writer when something is sent down it,
+            // otherwise the next command reads nothing at all
+            let next_command_pipe = if is_last_command {
                 None
+            } else {
+                let (next_command_reader, next_command_writer) = io::pipe()?;
+                previous_commands_stdout_reader = Some(next_command_reader);
+                Some(next_command_writer)
             };
-            let mut next_command_io =
-                CommandIO::new(command_io_stdin, stdout_writer, stderr_writer);
-            let command_result = match command.builtin_command {
-                BuiltinCommand::ChangeDirectory(arguments) => {
-                    change_directory(&arguments, next_command_io)
-                }
-                BuiltinCommand::Echo(arguments) => echo(&arguments, next_command_io),
-                BuiltinCommand::Exit => {
-                    let history_file_path = History::get_history_file_path()?;
-                    history.write_history_to_file(next_command_io, &history_file_path)?;
-                    break 'repl_loop;
-                }
-                BuiltinCommand::History(arguments) => {
-                    history.controller(next_command_io, arguments.into())
-                }
-                BuiltinCommand::PWD => pwd(next_command_io),
-                BuiltinCommand::Type(arguments) => builtin_type(arguments, &path, next_command_io),
+            let file_descriptors = &command.file_descriptors;
+            let stdout = file_descriptors
+                .standard_out
+                .open_writer(next_command_pipe.as_ref())?;
+            let stderr = file_descriptors
+                .standard_error
+                .open_writer(next_command_pipe.as_ref())?;
+
+            // `3>&1` is a copy of where standard out went before any of its own redirects,
+            // so it stays open after `>&-`
+            let shell_standard_out = io::stdout();
+            let standard_out = match &next_command_pipe {
+                Some(next_command_pipe) => next_command_pipe.as_fd(),
+                None => shell_standard_out.as_fd(),
+            };
+            let other_descriptors =
+                file_descriptors.duplicate_others(standard_out, io::stderr().as_fd())?;
+            drop(next_command_pipe);
+
+            let mut next_command_io = CommandIO::new(stdin, stdout, stderr);
+            next_command_io.other_descriptors = other_descriptors;
+            let process_group = self.jobs.process_group(&job, is_foreground);
+
+            match command.builtin_command {
                 BuiltinCommand::NotFound(command_name, arguments) => {
                     if let Some(_executable) =
-                        find_executable_files(&command_name, &path, false)?.first()
+                        find_executable_files(&command_name, &self.path, false)?.first()
                     {
-                        let mut child = run_external(
-                            command_name,
-                            arguments,
-                            next_command_io,
-                            commands.is_empty(),
-                            !command.standard_out.is_standard(),
-                        )?;
-
-                        if commands.is_empty() {
-                            let exited_child = child.wait()?;
-                            if !exited_child.success() {
-                                Err(ErrorExitCode::new(exited_child.code().unwrap()))
-                            } else {
-                                Ok(())
-                            }
-                        } else {
-                            previous_external_child = Some(child);
-                            Ok(())
-                        }
+                        let child =
+                            run_external(command_name, arguments, next_command_io, process_group)?;
+                        job.add_process(child.id() as libc::pid_t, process_group, command_text);
                     } else {
-                        writeln!(next_command_io.stderr, "{command_name}: command not found")?;
+                        // standard error may well be closed, which is no reason to stop
+                        let _ =
+                            writeln!(next_command_io.stderr, "{command_name}: command not found");
                         drop(next_command_io.stderr);
                         drop(next_command_io.stdout);
-                        Err(ErrorExitCode::new_const::<2>())
+                        job.add_finished(2, command_text);
                     }
                 }
-            };
-
-            match command_result {
-                Ok(()) => {
-                    let exit_code = 0;
-
-                    previous_commands_stdout_reader = Some(stdout_reader);
-                    unsafe {
-                        env::set_var("?", exit_code.to_string());
-                    }
-
-                    if commands.is_empty() {
-                        match &current_command.standard_error {
-                            command::Output::Standard => {
-                                io::copy(&mut stderr_reader, &mut io::stderr())?;
-                            }
-                            command::Output::CreateFile(filename) => {
-                                let mut reader = BufReader::new(stderr_reader);
-                                let mut buffer = reader.fill_buf()?;
-                                utilities::write_all_to_file(&mut buffer, filename)?;
-                            }
-                            command::Output::AppendFile(filename) => {
-                                let mut reader = BufReader::new(stderr_reader);
-                                let buffer = reader.fill_buf()?;
-                                utilities::append_all_to_file(&buffer, filename)?;
-                            }
+                // a builtin in the background or anywhere in a longer pipeline runs in a
+                // forked copy, so `echo | exit` doesn't end the shell, just like in bash
+                builtin_command if is_single_command && is_foreground => {
+                    match self.run_builtin(builtin_command, file_descriptors, next_command_io)? {
+                        ControlFlow::Continue(Ok(())) => job.add_finished(0, command_text),
+                        ControlFlow::Continue(Err(code)) => {
+                            job.add_finished(code.code(), command_text)
                         }
+                        ControlFlow::Break(()) => return Ok(ControlFlow::Break(())),
                     }
                 }
-                Err(code) => {
-                    unsafe { env::set_var("?", code.to_string()) }
-                    match &current_command.standard_error {
-                        command::Output::Standard => {
-                            io::copy(&mut stderr_reader, &mut io::stderr())?;
-                        }
-                        command::Output::CreateFile(filename) => {
-                            let mut reader = BufReader::new(stderr_reader);
-                            let mut buffer = reader.fill_buf()?;
-                            utilities::write_all_to_file(&mut buffer, filename)?;
-                        }
-                        command::Output::AppendFile(filename) => {
-                            let mut reader = BufReader::new(stderr_reader);
-                            let buffer = reader.fill_buf()?;
-                            utilities::append_all_to_file(&buffer, filename)?;
-                        }
-                    }
-
-                    match &current_command.standard_out {
-                        command::Output::Standard => {
-                            io::copy(&mut stdout_reader, &mut io::stderr())?;
-                        }
-                        command::Output::CreateFile(filename) => {
-                            let mut reader = BufReader::new(stdout_reader);
-                            let mut buffer = reader.fill_buf()?;
-                            utilities::write_all_to_file(&mut buffer, filename)?;
-                        }
-                        command::Output::AppendFile(filename) => {
-                            let mut reader = BufReader::new(stdout_reader);
-                            let buffer = reader.fill_buf()?;
-                            utilities::append_all_to_file(&buffer, filename)?;
-                        }
-                    }
+                builtin_command => {
+                    let child_pid = self.fork_builtin(
+                        builtin_command,
+                        file_descriptors,
+                        next_command_io,
+                        process_group,
+                        &mut previous_commands_stdout_reader,
+                    )?;
+                    job.add_process(child_pid, process_group, command_text);
                 }
             }
+        }
 
-            last_command = Some(current_command);
+        if !is_foreground {
+            self.jobs.run_in_background(job);
+            self.pipe_status = vec![0];
+            unsafe { env::set_var("?", "0") }
+            return Ok(ControlFlow::Continue(()));
         }
 
-        if let Some(mut stdout) = previous_commands_stdout_reader {
-            if let Some(last_command) = last_command {
-                match last_command.standard_out {
-                    command::Output::Standard => {
-                        io::copy(&mut stdout, &mut io::stdout())?;
-                    }
-                    command::Output::CreateFile(filename) => {
-                        let mut reader = BufReader::new(stdout);
-                        let mut buffer = reader.fill_buf()?;
-                        utilities::write_all_to_file(&mut buffer, &filename)?;
-                    }
-                    command::Output::AppendFile(filename) => {
-                        let mut reader = BufReader::new(stdout);
-                        let buffer = reader.fill_buf()?;
-                        utilities::append_all_to_file(&buffer, &filename)?;
-                    }
+        // every command is waited for, even when only the last one decides the exit code,
+        // so none of them are left behind as zombies
+        self.pipe_status = self.jobs.run_in_foreground(job)?;
+
+        let exit_code = if self.options.pipefail {
+            // the last command that failed, or 0 when they all succeeded
+            self.pipe_status
+                .iter()
+                .rfind(|exit_code| **exit_code != 0)
+                .copied()
+                .unwrap_or(0)
+        } else {
+            self.pipe_status.last().copied().unwrap_or(0)
+        };
+
+        unsafe { env::set_var("?", exit_code.to_string()) }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Runs a builtin in the shell itself, breaking when the shell should exit.
+    fn run_builtin(
+        &mut self,
+        builtin_command: BuiltinCommand,
+        file_descriptors: &FileDescriptors,
+        next_command_io: CommandIO,
+    ) -> Result<ControlFlow<(), Result<(), ErrorExitCode>>> {
+        let builtin_name = builtin_command.name().to_owned();
+        let mut standard_error = next_command_io.stderr.try_clone()?;
+        let command_result = match builtin_command {
+            BuiltinCommand::Arithmetic(expression) => arithmetic(&expression, next_command_io),
+            BuiltinCommand::Bg(arguments) => bg(&arguments, &mut self.jobs, next_command_io),
+            BuiltinCommand::ChangeDirectory(arguments) => {
+                change_directory(&arguments, next_command_io)
+            }
+            BuiltinCommand::Echo(arguments) => echo(&arguments, next_command_io),
+            BuiltinCommand::Exec(arguments) => exec(
+                &arguments,
+                file_descriptors,
+                &mut self.file_descriptors,
+                &self.path,
+                next_command_io,
+            ),
+            BuiltinCommand::Exit => {
+                if !self.subshell {
+                    let history_file_path = History::get_history_file_path()?;
+                    self.history
+                        .write_history_to_file(next_command_io, &history_file_path)?;
                 }
-            };
+                return Ok(ControlFlow::Break(()));
+            }
+            BuiltinCommand::Fg(arguments) => fg(&arguments, &mut self.jobs, next_command_io),
+            BuiltinCommand::History(arguments) => {
+                self.history
+                    .controller(next_command_io, arguments.into(), self.options.extglob)
+            }
+            BuiltinCommand::Jobs(arguments) => jobs(&arguments, &mut self.jobs, next_command_io),
+            BuiltinCommand::Pwd => pwd(next_command_io),
+            BuiltinCommand::Read(arguments) => read(&arguments, next_command_io),
+            BuiltinCommand::Set(arguments) => set(&arguments, &mut self.options, next_command_io),
+            BuiltinCommand::Shopt(arguments) => {
+                shopt(&arguments, &mut self.options, next_command_io)
+            }
+            BuiltinCommand::Type(arguments) => builtin_type(arguments, &self.path, next_command_io),
+            BuiltinCommand::NotFound(command_name, _) => {
+                unreachable!("{command_name} is not a builtin")
+            }
+        };
+
+        // a builtin that can't read or write says why, like bash's do
+        if let Err(error_exit_code) = &command_result
+            && let Some(error) = error_exit_code.io_error()
+        {
+            let _ = writeln!(
+                standard_error,
+                "{builtin_name}: {}",
+                describe_io_error(error)
+            );
         }
+
+        Ok(ControlFlow::Continue(command_result))
     }
 
-    Ok(())
+    /// Runs a builtin that is part of a longer pipeline or in the background in a forked copy
+    /// of the shell, so it runs alongside the other commands instead of filling up the pipe
+    /// before they start. Like in bash, anything it changes about the shell doesn't last.
+    fn fork_builtin(
+        &mut self,
+        builtin_command: BuiltinCommand,
+        file_descriptors: &FileDescriptors,
+        next_command_io: CommandIO,
+        process_group: Option<ProcessGroup>,
+        next_command_reader: &mut Option<PipeReader>,
+    ) -> Result<libc::pid_t> {
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error().into()),
+            0 => {
+                // holding the other end of its own output would keep the builtin writing
+                // after the next command has stopped reading, instead of it seeing EPIPE
+                drop(next_command_reader.take());
+                // and like any other command, writing to a pipe nobody reads ends it
+                unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+                if let Some(process_group) = process_group {
+                    let _ = process_group.join();
+                }
+
+                release_interrupts();
+                self.enter_subshell();
+                let exit_code =
+                    match self.run_builtin(builtin_command, file_descriptors, next_command_io) {
+                        Ok(ControlFlow::Continue(Ok(()))) => 0,
+                        Ok(ControlFlow::Continue(Err(code))) => code.code(),
+                        Ok(ControlFlow::Break(())) => last_exit_code(),
+                        Err(error) => {
+                            eprintln!("{error}");
+                            1
+                        }
+                    };
+
+                let _ = io::stdout().flush();
+                unsafe { libc::_exit(exit_code) }
+            }
+            child_pid => Ok(child_pid),
+        }
+    }
 }
@@ -5,8 +5,11 @@ use std::{
     env::{self, split_paths},
     fs::DirEntry,
     io::{self, Write},
-    os::unix::fs::MetadataExt,
-    path::{Path, PathBuf},
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::fs::MetadataExt,
+    },
+    path::PathBuf,
 };
 
 pub fn print_prompt() {
@@ -82,30 +85,6 @@ pub fn find_executable_files(
     Ok(executable_files)
 }
 
-pub fn write_all_to_file(buffer: &[u8], filename: &str) -> Result<()> {
-    let file_path = Path::new(filename);
-    let mut file = std::fs::File::options()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(file_path)?;
-
-    file.write_all(buffer)?;
-    Ok(())
-}
-
-pub fn append_all_to_file(buffer: &[u8], filename: &str) -> Result<()> {
-    let file_path = Path::new(filename);
-    let mut file = std::fs::File::options()
-        .create(true)
-        .append(true)
-        .open(file_path)?;
-
-    file.write_all(buffer)?;
-
-    Ok(())
-}
-
 pub fn find_matching_builtin(partial: &str) -> Result<Option<String>> {
     let matching_builtins = BuiltinCommand::matches(partial);
 
@@ -161,3 +140,34 @@ pub fn calculate_longest_common_prefix(word: &str, list: &[String]) -> Vec<(usiz
         })
         .collect()
 }
+
+/// A copy of `fd` numbered `lowest` or above, which is closed when the shell runs another
+/// program unless it gets moved into place with [`move_descriptor`]
+pub fn duplicate_above(fd: BorrowedFd, lowest: RawFd) -> io::Result<OwnedFd> {
+    let copy = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, lowest) };
+
+    if copy == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // fcntl just gave us this descriptor and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(copy) })
+}
+
+/// Makes `fd` refer to the same thing as `source`, or closes it when there is no source.
+/// It only makes system calls, so it's fine to run between forking and running a program.
+pub fn move_descriptor(fd: RawFd, source: Option<RawFd>) -> io::Result<()> {
+    match source {
+        Some(source) => {
+            if unsafe { libc::dup2(source, fd) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        // closing an fd that was never open isn't a problem
+        None => unsafe {
+            libc::close(fd);
+        },
+    }
+
+    Ok(())
+}